@@ -1,9 +1,11 @@
 mod utils;
 
+use std::process::ExitCode;
+
 use utils::parser;
 
-fn main() {
+fn main() -> ExitCode {
     let mut parser = parser::parse();
 
-    parser.setup();
+    parser.setup()
 }
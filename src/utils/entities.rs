@@ -1,75 +1,372 @@
 use crossterm::style::Stylize;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{
+    git::{self, Status},
+    health,
+    layers::{self, Layer, Origin},
+    pipeline::{self, Step},
+    runner::{CommandRunner, DryRunRunner, SystemRunner},
+    settings, wizard,
+};
 use std::{
+    collections::BTreeMap,
+    env,
     error::Error,
     fmt::Display,
-    io::{Read, Write},
-    process::{Command, Stdio},
+    io::{self, IsTerminal},
+    path::PathBuf,
+    process::{ExitCode, ExitStatus},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 #[derive(Debug)]
 pub enum LateremError {
-    InvalidArgument,
+    /// wrong or missing CLI values
+    InvalidArgument(String),
+    /// a command couldn't be started or its output couldn't be read
+    Spawn { command: String, source: io::Error },
+    /// a command exited with a non-zero status
+    Failed {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// a command attached to the terminal exited with a non-zero `code`, laterem exits with it
+    Exited { command: String, code: u8 },
+    /// a config file couldn't be read or written
+    ConfigIo { path: PathBuf, source: io::Error },
+    /// a config file isn't valid JSON or doesn't follow the expected structure
+    ConfigParse { path: PathBuf, reason: String },
+    /// a config value is unknown or has the wrong type
+    InvalidConfig(String),
+    /// the default branch wasn't configured and couldn't be detected
+    MissingDefaultBranch,
+    /// the current directory isn't inside a git repository
+    NotGitRepository,
+    /// the repository couldn't be read
+    Git(git2::Error),
+    /// a rebase or merge stopped on conflicts, `stash` holding the uncommitted changes
+    Conflict {
+        operation: String,
+        branch: String,
+        stash: Option<String>,
+    },
+    /// a query printed something laterem doesn't understand
+    UnexpectedOutput { command: String, reason: String },
+    /// compose services that didn't become healthy within `timeout` seconds
+    Unhealthy { services: Vec<String>, timeout: u64 },
+    /// compose services whose container exited with an error before becoming healthy
+    Stopped { services: Vec<String> },
+    /// the confirmation of a destructive action was declined
+    Cancelled,
 }
 
-impl Error for LateremError {}
+impl LateremError {
+    /// Process exit code, distinct for usage, config, git and docker failures
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            LateremError::InvalidArgument(_) => 2,
+            LateremError::ConfigIo { .. }
+            | LateremError::ConfigParse { .. }
+            | LateremError::InvalidConfig(_) => 3,
+            LateremError::MissingDefaultBranch
+            | LateremError::NotGitRepository
+            | LateremError::Git(_)
+            | LateremError::Conflict { .. } => 4,
+            LateremError::Unhealthy { .. } | LateremError::Stopped { .. } => 5,
+            LateremError::Cancelled => 1,
+            LateremError::Exited { code, .. } => *code,
+            LateremError::Spawn { command, .. }
+            | LateremError::Failed { command, .. }
+            | LateremError::UnexpectedOutput { command, .. } => match command.split(' ').next() {
+                Some("git") => 4,
+                Some("docker") => 5,
+                _ => 1,
+            },
+        }
+    }
+
+    /// Suggestion printed below the error
+    pub fn hint(&self) -> String {
+        match self {
+            LateremError::InvalidArgument(_) => {
+                String::from("run `laterem --help` to see the accepted values")
+            }
+            LateremError::Spawn { command, .. } => format!(
+                "make sure `{}` is installed and available in your PATH",
+                command.split(' ').next().unwrap_or_default()
+            ),
+            LateremError::Failed { command, .. } if command.starts_with("docker ") => {
+                String::from("make sure the docker daemon is running and the compose file is valid")
+            }
+            LateremError::Failed { .. } => String::from(
+                "the remaining steps were skipped, check `git status` and `git stash list` before retrying",
+            ),
+            LateremError::Exited { .. } => String::from("the exit code is the one of the command"),
+            LateremError::ConfigIo { path, .. } => {
+                format!("check that {} is accessible", path.display())
+            }
+            LateremError::ConfigParse { path, .. } => format!(
+                "fix {} or remove it to go through the first run setup again",
+                path.display()
+            ),
+            LateremError::InvalidConfig(_) => {
+                String::from("run `laterem config validate` to check every config file")
+            }
+            LateremError::MissingDefaultBranch => String::from(
+                "set it with `laterem config set branch <name>` or `git remote set-head origin --auto`",
+            ),
+            LateremError::NotGitRepository => {
+                String::from("run laterem from inside a git repository")
+            }
+            LateremError::Git(_) => {
+                String::from("check the state of the repository with `git status`")
+            }
+            LateremError::Conflict {
+                operation, stash, ..
+            } => {
+                let hint = format!(
+                    "fix the conflicts and run `git {0} --continue`, or `git {0} --abort` to go back",
+                    operation
+                );
+
+                match stash {
+                    Some(stash) => format!(
+                        "{}, then restore your uncommitted changes with `git stash pop {}`",
+                        hint, stash
+                    ),
+                    None => hint,
+                }
+            }
+            LateremError::UnexpectedOutput { .. } => {
+                String::from("make sure docker compose v2 is installed, `docker compose version`")
+            }
+            LateremError::Unhealthy { .. } => String::from(
+                "check the log lines above, raise `wait_timeout` or set it to 0 to skip the wait",
+            ),
+            LateremError::Stopped { .. } => String::from(
+                "check the log lines above for why it exited, `laterem d logs <service>` prints them all",
+            ),
+            LateremError::Cancelled => {
+                String::from("run it again and answer `y`, or pass `--yes` to skip the question")
+            }
+        }
+    }
+}
+
+impl Error for LateremError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LateremError::Spawn { source, .. } | LateremError::ConfigIo { source, .. } => {
+                Some(source)
+            }
+            LateremError::Git(source) => Some(source),
+            _ => None,
+        }
+    }
+}
 
 impl Display for LateremError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            LateremError::InvalidArgument => write!(f, "invalid argument"),
+            LateremError::InvalidArgument(reason) => write!(f, "invalid argument: {}", reason),
+            LateremError::Spawn { command, source } => {
+                write!(f, "unable to run `{}`: {}", command, source)
+            }
+            LateremError::Exited { command, code } => {
+                write!(f, "`{}` exited with code {}", command, code)
+            }
+            LateremError::Failed {
+                command,
+                status,
+                stderr,
+            } => match stderr.lines().last() {
+                Some(line) => write!(f, "`{}` failed with {}: {}", command, status, line),
+                None => write!(f, "`{}` failed with {}", command, status),
+            },
+            LateremError::ConfigIo { path, source } => {
+                write!(f, "unable to access {}: {}", path.display(), source)
+            }
+            LateremError::ConfigParse { path, reason } => {
+                write!(f, "invalid config file {}: {}", path.display(), reason)
+            }
+            LateremError::InvalidConfig(reason) => write!(f, "invalid config: {}", reason),
+            LateremError::MissingDefaultBranch => write!(
+                f,
+                "unable to find the default branch, `origin/HEAD` isn't set and neither \
+                 `init.defaultBranch`, `main`, `master` nor `develop` exist"
+            ),
+            LateremError::NotGitRepository => write!(f, "not inside a git repository"),
+            LateremError::Git(error) => {
+                write!(f, "unable to read the repository: {}", error.message())
+            }
+            LateremError::Conflict {
+                operation, branch, ..
+            } => write!(
+                f,
+                "the {} of `{}` stopped on conflicts, your uncommitted changes were kept aside",
+                operation, branch
+            ),
+            LateremError::UnexpectedOutput { command, reason } => {
+                write!(f, "unable to read the output of `{}`: {}", command, reason)
+            }
+            LateremError::Unhealthy { services, timeout } => write!(
+                f,
+                "`{}` didn't become healthy within {}s",
+                services.join("`, `"),
+                timeout
+            ),
+            LateremError::Stopped { services } => write!(
+                f,
+                "`{}` exited with an error before becoming healthy",
+                services.join("`, `")
+            ),
+            LateremError::Cancelled => write!(f, "cancelled, nothing was removed"),
         }
     }
 }
 
+impl From<git2::Error> for LateremError {
+    fn from(error: git2::Error) -> Self {
+        LateremError::Git(error)
+    }
+}
+
+/// Missing fields in the config file fall back to the same values used by `Default`, an empty
+/// `branch` is only detected by `detect_missing` so deserializing never spawns a process
 #[derive(Serialize, Deserialize)]
 pub struct DefaultConfig {
-    branch: String,
-    stash_files: bool,
-    detach_container: bool,
+    #[serde(default)]
+    pub branch: String,
+    #[serde(default = "origin")]
+    pub remote: String,
+    #[serde(default = "enabled")]
+    pub stash_files: bool,
+    #[serde(default = "enabled")]
+    pub detach_container: bool,
+    /// merged by compose in order, an empty list keeps compose's own lookup
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub compose_files: Vec<String>,
+    #[serde(default)]
+    pub project_name: Option<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub env_files: Vec<String>,
+    /// passed as `--project-directory`, the relative paths inside the compose files resolve from it
+    /// while `compose_files` and `env_files` still resolve from the current directory
+    #[serde(default)]
+    pub working_directory: Option<String>,
+    #[serde(default)]
+    pub sync_strategy: SyncStrategy,
+    /// seconds `up` and `reset` wait for the services to be healthy, 0 skipping the wait
+    #[serde(default = "wait_timeout")]
+    pub wait_timeout: u64,
+    /// local TCP port of a service that has to accept connections before it counts as healthy
+    #[serde(default)]
+    pub wait_ports: BTreeMap<String, u16>,
 }
 
-impl Default for DefaultConfig {
-    fn default() -> Self {
-        let mut git = Command::new("git")
-            .args(["remote", "show", "origin"])
-            .stdout(Stdio::piped())
-            .spawn()
-            .expect("Unable to spawn GIT instance");
-
-        let mut sed = Command::new("sed")
-            .args(["-n", "/HEAD branch/s/.*: //p"])
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()
-            .expect("Unable to spawn SED instance");
-
-        if let Some(ref mut git_stdout) = git.stdout {
-            if let Some(ref mut stdin) = sed.stdin {
-                let mut buffer: Vec<u8> = Vec::new();
-
-                git_stdout
-                    .read_to_end(&mut buffer)
-                    .expect("Unable to read STDOUT from GIT instance");
-                stdin
-                    .write_all(&buffer)
-                    .expect("Unable to write to STDIN using SED instance");
-            }
-        }
+/// How `sync` brings the current branch up to date with the default one
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncStrategy {
+    #[default]
+    Rebase,
+    Merge,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Accept a single string where a list is expected, e.g: `LATEREM_COMPOSE_FILES=compose.yml`
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+fn wait_timeout() -> u64 {
+    60
+}
 
-        let _ = git.wait().unwrap();
-        let origin = sed
-            .wait_with_output()
-            .expect("Unable to retrieve STDOUT from SED instance")
-            .stdout;
-        let origin = String::from_utf8(origin)
-            .expect("Unable to convert origin buffer into a utf8 string")
-            .replace("\n", "");
+fn origin() -> String {
+    String::from("origin")
+}
 
+impl Default for DefaultConfig {
+    fn default() -> Self {
         Self {
-            branch: origin,
+            branch: DefaultConfig::detect_branch(&origin()).unwrap_or_default(),
+            remote: origin(),
             stash_files: true,
             detach_container: true,
+            compose_files: Vec::new(),
+            project_name: None,
+            env_files: Vec::new(),
+            working_directory: None,
+            sync_strategy: SyncStrategy::Rebase,
+            wait_timeout: wait_timeout(),
+            wait_ports: BTreeMap::new(),
+        }
+    }
+}
+
+impl DefaultConfig {
+    /// Every field as a JSON object, keyed by the names used in the config file
+    pub fn values(&self) -> Map<String, Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(values)) => values,
+            _ => Map::new(),
+        }
+    }
+
+    /// Names of every field, as used in the config file
+    pub fn keys() -> Vec<String> {
+        DefaultConfig::check(&Map::new())
+            .map(|defaults| defaults.values().keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Deserialize `values` without detecting anything, unknown keys are ignored
+    pub fn check(values: &Map<String, Value>) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(Value::Object(values.clone()))
+    }
+
+    /// Detect every field that was left unset by the config layers
+    pub fn detect_missing(&mut self) -> Result<(), LateremError> {
+        if self.branch.is_empty() {
+            self.branch = DefaultConfig::detect_branch(&self.remote)?;
+        }
+
+        Ok(())
+    }
+
+    /// Detect the default branch of the repository without touching the network, empty outside a
+    /// repository or when nothing was found
+    pub fn detect_branch(remote: &str) -> Result<String, LateremError> {
+        let repository = match git::open() {
+            Ok(repository) => repository,
+            Err(LateremError::NotGitRepository) => return Ok(String::new()),
+            Err(error) => return Err(error),
+        };
+
+        Ok(git::detect_default_branch(&repository, remote).unwrap_or_default())
+    }
+
+    /// Keep the detected branch in the local git config, see [`git::cache_default_branch`]
+    fn cache_branch(&self) {
+        if let Ok(repository) = git::open() {
+            git::cache_default_branch(&repository, &self.remote, &self.branch);
         }
     }
 }
@@ -78,6 +375,7 @@ pub enum Target {
     Docker,
     Repository,
     RepositoryCurrent,
+    Config,
 }
 
 impl Display for Target {
@@ -86,6 +384,7 @@ impl Display for Target {
             Target::Docker => write!(f, "docker"),
             Target::Repository => write!(f, "repository"),
             Target::RepositoryCurrent => write!(f, "current"),
+            Target::Config => write!(f, "config"),
         }
     }
 }
@@ -98,6 +397,14 @@ pub enum Action {
     Down,
     /// docker compose up -d
     Up,
+    /// docker compose logs
+    Logs,
+    /// table of the compose services
+    Ps,
+    /// docker compose exec, an interactive shell by default
+    Exec,
+    /// docker compose down with the volumes and orphans, then up
+    Nuke,
 
     /// git commit -m "$1" && git push origin $branch
     Commit,
@@ -105,6 +412,21 @@ pub enum Action {
     Push,
     /// git pull origin $branch
     Pull,
+    /// update the default branch, then rebase or merge the current one onto it
+    Sync,
+
+    /// print a single config value
+    Get,
+    /// write a config value
+    Set,
+    /// remove a config value
+    Unset,
+    /// print every effective config value
+    List,
+    /// open the config file with $EDITOR
+    Edit,
+    /// check every config file for unknown keys and invalid values
+    Validate,
 }
 
 impl Display for Action {
@@ -113,457 +435,688 @@ impl Display for Action {
             Action::Reset => write!(f, "reset"),
             Action::Down => write!(f, "down"),
             Action::Up => write!(f, "up"),
+            Action::Logs => write!(f, "logs"),
+            Action::Ps => write!(f, "ps"),
+            Action::Exec => write!(f, "exec"),
+            Action::Nuke => write!(f, "nuke"),
             Action::Commit => write!(f, "commit"),
             Action::Push => write!(f, "push"),
             Action::Pull => write!(f, "pull"),
+            Action::Sync => write!(f, "sync"),
+            Action::Get => write!(f, "get"),
+            Action::Set => write!(f, "set"),
+            Action::Unset => write!(f, "unset"),
+            Action::List => write!(f, "list"),
+            Action::Edit => write!(f, "edit"),
+            Action::Validate => write!(f, "validate"),
         }
     }
 }
 
 pub struct Config {
-    pub path: String,
+    pub path: PathBuf,
     pub target: Target,
     pub action: Action,
     pub defaults: Option<Box<DefaultConfig>>,
     pub arguments: Vec<String>,
+    pub values: Vec<String>,
+    pub local: bool,
+    pub profile: Option<String>,
+    pub interactive: bool,
+    pub show_origin: bool,
+    pub dry_run: bool,
+    /// make `sync` rebase whatever `sync_strategy` says
+    pub rebase: bool,
+    /// keep `logs` running, printing the new lines
+    pub follow: bool,
+    /// only print the `logs` lines since a timestamp or a duration
+    pub since: Option<String>,
+    /// make `nuke` rebuild the images without the build cache
+    pub no_cache: bool,
+    /// skip the confirmation of `nuke`
+    pub yes: bool,
+    /// config values given as flags, layered above every other source
+    pub overrides: Map<String, Value>,
+    pub origins: BTreeMap<String, Origin>,
+}
+
+#[cfg(test)]
+impl Config {
+    /// A config of `target` and `action` with every flag unset and nothing loaded yet, tests
+    /// override the fields they care about
+    pub fn for_test(target: Target, action: Action) -> Self {
+        Self {
+            path: PathBuf::from("config.json"),
+            target,
+            action,
+            defaults: None,
+            arguments: Vec::new(),
+            values: Vec::new(),
+            local: false,
+            profile: None,
+            interactive: false,
+            show_origin: false,
+            dry_run: false,
+            rebase: false,
+            follow: false,
+            since: None,
+            no_cache: false,
+            yes: false,
+            overrides: Map::new(),
+            origins: BTreeMap::new(),
+        }
+    }
 }
 
 impl Action {
-    pub fn run(config: &Config) -> Result<(), LateremError> {
-        println!(
-            "{}\t\n",
-            " RUNNING ACTIONS ".on_dark_magenta().white().bold()
-        );
+    pub fn run(config: &Config, runner: &mut dyn CommandRunner) -> Result<(), LateremError> {
+        let banner = if config.dry_run {
+            " PLANNED ACTIONS "
+        } else {
+            " RUNNING ACTIONS "
+        };
+        println!("{}\t\n", banner.on_dark_magenta().white().bold());
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let stash = format!("laterem:{}:{}", config.action, timestamp);
+
+        let steps = Action::plan(config, runner, &stash)?;
+
+        pipeline::run(&steps, runner).map_err(|error| conflict(error, runner, &stash))?;
+
+        match config.action {
+            Action::Ps => Action::print_services(config, runner),
+            _ => config.action.wait(config, runner),
+        }
+    }
+
+    /// Print the table of the compose services, `ps` only reads so it has no step
+    fn print_services(config: &Config, runner: &mut dyn CommandRunner) -> Result<(), LateremError> {
+        let compose = compose(loaded(config)?);
+
+        health::print(runner, &compose, &requested(config))
+    }
+
+    /// Wait for the services started by a detached `up` or `reset` to be healthy
+    fn wait(&self, config: &Config, runner: &mut dyn CommandRunner) -> Result<(), LateremError> {
+        let defaults = loaded(config)?;
+        let started = matches!(config.target, Target::Docker)
+            && matches!(self, Action::Reset | Action::Up | Action::Nuke)
+            && defaults.detach_container;
+
+        if !started || defaults.wait_timeout == 0 || runner.dry_run() {
+            return Ok(());
+        }
+
+        health::wait(
+            runner,
+            &compose(defaults),
+            &requested(config),
+            &defaults.wait_ports,
+            Duration::from_secs(defaults.wait_timeout),
+        )
+    }
+
+    /// Every step of the selected target and action, `stash` being the message of the stash
+    /// created to keep uncommitted changes
+    fn plan(
+        config: &Config,
+        runner: &mut dyn CommandRunner,
+        stash: &str,
+    ) -> Result<Vec<Step>, LateremError> {
+        let defaults = loaded(config)?;
 
         match config.target {
-            Target::Docker => match &config.action {
-                Action::Reset => {
-                    println!(
-                        "{}{}{}{}",
-                        "Taking instance down".dim(),
-                        ".".rapid_blink(),
-                        ".".rapid_blink(),
-                        ".".dim(),
-                    );
-                    Command::new("docker")
-                        .args(["compose", "down"])
-                        .status()
-                        .expect("Didn't manage to take the instance down");
+            Target::Docker => config.action.docker_steps(config, defaults, runner),
+            Target::Repository | Target::RepositoryCurrent => {
+                let needs_default = matches!(config.target, Target::Repository)
+                    || matches!(config.action, Action::Sync);
 
-                    println!(
-                        "\t\n{}{}{}{}",
-                        "Launching a new instance".dim(),
-                        ".".rapid_blink(),
-                        ".".rapid_blink(),
-                        ".".dim(),
-                    );
-                    Command::new("docker")
-                        .args(["compose", "up", "-d"])
-                        .status()
-                        .expect("Didn't manage to create a new instance");
+                // Outside a repository the branch is empty too, that error comes first
+                let status = runner.status()?;
 
-                    Ok(())
+                if needs_default && defaults.branch.is_empty() {
+                    return Err(LateremError::MissingDefaultBranch);
                 }
-                Action::Down => {
-                    println!(
-                        "{}{}{}{}",
-                        "Taking instance down".dim(),
-                        ".".rapid_blink(),
-                        ".".rapid_blink(),
-                        ".".dim(),
-                    );
-                    Command::new("docker")
-                        .args(["compose", "down"])
-                        .status()
-                        .expect("Didn't manage to take the instance down");
 
-                    Ok(())
+                // Only a run that changes the repository remembers a detected branch
+                let detected = matches!(config.origins.get("branch"), Some(Origin::Default));
+                if detected && !defaults.branch.is_empty() && !runner.dry_run() {
+                    defaults.cache_branch();
                 }
-                Action::Up => {
-                    println!(
-                        "{}{}{}{}",
-                        "Launching a new instance".dim(),
-                        ".".rapid_blink(),
-                        ".".rapid_blink(),
-                        ".".dim(),
-                    );
-                    Command::new("docker")
-                        .args(["compose", "up", "-d"])
-                        .status()
-                        .expect("Didn't manage to create a new instance");
 
-                    Ok(())
-                }
-                _ => Err(LateremError::InvalidArgument),
-            },
-            Target::Repository => {
-                let defaults = config
-                    .defaults
-                    .as_ref()
-                    .expect("Unable to retrieve the default configuration");
-                let args: Vec<&str> = config.arguments.iter().map(|item| item.as_str()).collect();
-
-                let branch = Command::new("git")
-                    .args(["branch", "--show-current"])
-                    .output()
-                    .expect("Didn't manage to retrieve the active branch");
-                let branch = String::from_utf8(branch.stdout)
-                    .expect("Unable to convert the stdout response");
-                let branch = branch.replace("\n", "");
-
-                match &config.action {
-                    Action::Reset => {
-                        println!(
-                            "{}{}{}{}",
-                            "Staging files".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["add", "."])
-                            .status()
-                            .expect("Couldn't stage the changed files");
-
-                        println!(
-                            "\t\n{}{}{}{}",
-                            "Stashing staged files".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["stash"])
-                            .status()
-                            .expect("Couldn't stash the changes");
-
-                        println!(
-                            "\t\n{} {}{}{}{}",
-                            "Checking out to".dim(),
-                            defaults.branch.clone().magenta(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["checkout", &defaults.branch])
-                            .status()
-                            .expect("Unable to go back to the main branch");
-
-                        println!(
-                            "\t\n{} {} {}{}{}",
-                            "Pulling changes from".dim(),
-                            defaults.branch.clone().magenta(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["pull", "origin", &defaults.branch])
-                            .status()
-                            .expect("Unable to pull the updates");
-
-                        println!(
-                            "\t\n{} {}{}{}{}",
-                            "Going back to original branch".dim(),
-                            branch.clone().magenta(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["checkout", &branch])
-                            .status()
-                            .expect("Unable to checkout to the old branch");
-
-                        println!(
-                            "\t\n{}{}{}{}",
-                            "Popping stash".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["stash", "pop"])
-                            .status()
-                            .expect("Couldn't pop the stash");
-
-                        Ok(())
-                    }
-                    Action::Commit => {
-                        if args.is_empty() {
-                            return Err(LateremError::InvalidArgument);
-                        }
-
-                        println!(
-                            "{} {}{}{}{}",
-                            "Committing staged changes to".dim(),
-                            branch.clone().magenta(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args([["commit", "-m"].to_vec(), args].concat())
-                            .status()
-                            .expect("Unable to commit files");
-
-                        Ok(())
-                    }
-                    Action::Push => {
-                        println!(
-                            "{} {}{}{}{}",
-                            "Pushing committed changes to".dim(),
-                            branch.clone().magenta(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["push", "origin", branch.as_str()])
-                            .status()
-                            .expect("Unable to commit the files");
-
-                        Ok(())
-                    }
-                    Action::Pull => {
-                        println!(
-                            "{}{}{}{}",
-                            "Staging changes".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["add", "*"])
-                            .status()
-                            .expect("Unable to stage the files");
-
-                        println!(
-                            "\t\n{}{}{}{}",
-                            "Stashing staged changes".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["stash"])
-                            .status()
-                            .expect("Unable to stash the staged files");
-
-                        println!(
-                            "\t\n{} {}{}{}{}",
-                            "Pulling changes from".dim(),
-                            branch.clone().magenta(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["pull", "origin", &branch])
-                            .status()
-                            .expect("Unable to pull the updates");
-
-                        println!(
-                            "\t\n{}{}{}{}",
-                            "Popping stash".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["stash", "pop"])
-                            .status()
-                            .expect("Unable to pop the stash");
-
-                        Ok(())
-                    }
-                    _ => Err(LateremError::InvalidArgument),
+                print_status(&status);
+
+                config
+                    .action
+                    .repository_steps(config, defaults, &status, stash)
+            }
+            Target::Config => Err(LateremError::InvalidArgument(String::from(
+                "config actions don't run through Action::run",
+            ))),
+        }
+    }
+
+    /// Steps of `Target::Docker`, scoped to the services given as values or with `--args`
+    fn docker_steps(
+        &self,
+        config: &Config,
+        defaults: &DefaultConfig,
+        runner: &mut dyn CommandRunner,
+    ) -> Result<Vec<Step>, LateremError> {
+        let compose = compose(defaults);
+        let requested = requested(config);
+
+        if let Action::Exec = self {
+            let Some((service, command)) = requested.split_first() else {
+                return Err(LateremError::InvalidArgument(String::from(
+                    "missing the service to run the command in",
+                )));
+            };
+            check_services(runner, &compose, &[service])?;
+
+            let command = match command.is_empty() {
+                true => &SHELL[..],
+                false => command,
+            };
+            let exec = [&compose[..], &["exec", service], command].concat();
+
+            return Ok(vec![Step::docker("Running a command in", &exec)
+                .on(service)
+                .interactive()]);
+        }
+
+        if matches!(self, Action::Nuke) && !requested.is_empty() {
+            return Err(LateremError::InvalidArgument(String::from(
+                "`nuke` resets the whole compose project, it doesn't take services",
+            )));
+        }
+
+        let services = requested;
+        check_services(runner, &compose, &services)?;
+
+        let down = [compose.clone(), vec!["down"], services.clone()].concat();
+        let up = if defaults.detach_container {
+            [compose.clone(), vec!["up", "-d"], services.clone()].concat()
+        } else {
+            [compose.clone(), vec!["up"], services.clone()].concat()
+        };
+
+        let scoped = |step: Step| match services.is_empty() {
+            true => step,
+            false => step.on(&services.join(", ")),
+        };
+        let down = scoped(Step::docker("Taking instance down", &down));
+        let up = scoped(Step::docker("Launching a new instance", &up));
+
+        let mut logs = [compose.clone(), vec!["logs"]].concat();
+        if config.follow {
+            logs.push("--follow");
+        }
+        if let Some(since) = &config.since {
+            logs.extend(["--since", since.as_str()]);
+        }
+        logs.extend(&services);
+
+        match self {
+            Action::Reset => Ok(vec![down, up]),
+            Action::Down => Ok(vec![down]),
+            Action::Up => Ok(vec![up]),
+            Action::Logs if config.follow => Ok(vec![scoped(
+                Step::docker("Following the logs", &logs).interactive(),
+            )]),
+            Action::Logs => Ok(vec![scoped(Step::docker("Printing the logs", &logs))]),
+            Action::Ps => Ok(Vec::new()),
+            Action::Nuke => {
+                let volumes = volumes(runner, &compose)?;
+                confirm_nuke(config, runner, &volumes)?;
+
+                let down = [&compose[..], &["down", "--volumes", "--remove-orphans"]].concat();
+                let build = [&compose[..], &["build", "--no-cache"]].concat();
+
+                let mut steps = vec![Step::docker(
+                    "Removing the containers, volumes and orphans",
+                    &down,
+                )];
+                if config.no_cache {
+                    steps.push(Step::docker("Rebuilding the images without cache", &build));
                 }
+                steps.push(up);
+
+                Ok(steps)
             }
-            Target::RepositoryCurrent => {
-                let args: Vec<&str> = config.arguments.iter().map(|item| item.as_str()).collect();
-
-                let branch = Command::new("git")
-                    .args(["branch", "--show-current"])
-                    .output()
-                    .expect("Didn't manage to retrieve the active branch");
-                let branch = String::from_utf8(branch.stdout)
-                    .expect("Unable to convert the stdout response");
-                let branch = branch.replace("\n", "");
-
-                match &config.action {
-                    Action::Reset => {
-                        println!(
-                            "{}{}{}{}",
-                            "Staging files".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["add", "."])
-                            .status()
-                            .expect("Couldn't stage the changed files");
-
-                        println!(
-                            "\t\n{}{}{}{}",
-                            "Stashing staged files".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["stash"])
-                            .status()
-                            .expect("Couldn't stash the changes");
-
-                        println!(
-                            "\t\n{} {} {}{}{}",
-                            "Fetching branch".dim(),
-                            &branch,
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["fetch", "origin", &branch])
-                            .status()
-                            .expect("Unable to fetch the updates");
-
-                        println!(
-                            "\t\n{} {} {}{}{}",
-                            "Pulling changes from".dim(),
-                            &branch,
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["pull", "origin", &branch])
-                            .status()
-                            .expect("Unable to pull the updates");
-
-                        println!(
-                            "\t\n{}{}{}{}",
-                            "Popping stash".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["stash", "pop"])
-                            .status()
-                            .expect("Couldn't pop the stash");
-
-                        Ok(())
-                    }
-                    Action::Commit => {
-                        if args.is_empty() {
-                            return Err(LateremError::InvalidArgument);
-                        }
-
-                        println!(
-                            "{} {}{}{}{}",
-                            "Committing staged changes to".dim(),
-                            branch.clone().magenta(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args([["commit", "-m"].to_vec(), args].concat())
-                            .status()
-                            .expect("Unable to commit files");
-
-                        Ok(())
-                    }
-                    Action::Push => {
-                        println!(
-                            "{} {}{}{}{}",
-                            "Pushing committed changes to".dim(),
-                            branch.clone().magenta(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["push", "origin", branch.as_str()])
-                            .status()
-                            .expect("Unable to commit the files");
-
-                        Ok(())
-                    }
-                    Action::Pull => {
-                        println!(
-                            "{}{}{}{}",
-                            "Staging changes".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["add", "*"])
-                            .status()
-                            .expect("Unable to stage the files");
-
-                        println!(
-                            "\t\n{}{}{}{}",
-                            "Stashing staged changes".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["stash"])
-                            .status()
-                            .expect("Unable to stash the staged files");
-
-                        println!(
-                            "\t\n{} {}{}{}{}",
-                            "Pulling changes from".dim(),
-                            branch.clone().magenta(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["pull", "origin", &branch])
-                            .status()
-                            .expect("Unable to pull the updates");
-
-                        println!(
-                            "\t\n{}{}{}{}",
-                            "Popping stash".dim(),
-                            ".".rapid_blink(),
-                            ".".rapid_blink(),
-                            ".".dim(),
-                        );
-                        Command::new("git")
-                            .args(["stash", "pop"])
-                            .status()
-                            .expect("Unable to pop the stash");
-
-                        Ok(())
-                    }
-                    _ => Err(LateremError::InvalidArgument),
+            action => Err(LateremError::InvalidArgument(format!(
+                "`{}` isn't a docker action",
+                action
+            ))),
+        }
+    }
+
+    /// Steps of `Target::Repository` and `Target::RepositoryCurrent`, `status` being the state of
+    /// the checked out branch
+    fn repository_steps(
+        &self,
+        config: &Config,
+        defaults: &DefaultConfig,
+        status: &Status,
+        stash: &str,
+    ) -> Result<Vec<Step>, LateremError> {
+        let remote = defaults.remote.as_str();
+        let branch = status.branch.as_str();
+        let stashed = |pathspec, steps| {
+            if !defaults.stash_files || !status.dirty {
+                return steps;
+            }
+
+            stashed(pathspec, stash, steps)
+        };
+
+        match self {
+            Action::Reset if matches!(config.target, Target::RepositoryCurrent) => Ok(stashed(
+                ".",
+                vec![
+                    // `pull` fetches again, so a failed fetch is reported by it
+                    Step::git("Fetching branch", &["fetch", remote, branch])
+                        .on(branch)
+                        .optional(),
+                    Step::git("Pulling changes from", &["pull", remote, branch]).on(branch),
+                ],
+            )),
+            Action::Reset => {
+                let default = defaults.branch.as_str();
+
+                Ok(stashed(
+                    ".",
+                    vec![
+                        Step::git("Checking out to", &["checkout", default])
+                            .on(default)
+                            .undo(Step::git("Going back to", &["checkout", branch]).on(branch)),
+                        Step::git("Pulling changes from", &["pull", remote, default]).on(default),
+                        Step::git("Going back to original branch", &["checkout", branch])
+                            .on(branch),
+                    ],
+                ))
+            }
+            Action::Sync => {
+                let default = defaults.branch.as_str();
+                let rebase =
+                    config.rebase || matches!(defaults.sync_strategy, SyncStrategy::Rebase);
+                let update = match rebase {
+                    true => Step::git("Rebasing onto", &["rebase", default]),
+                    false => Step::git("Merging", &["merge", default]),
+                };
+
+                Ok(stashed(
+                    ".",
+                    vec![
+                        Step::git("Checking out to", &["checkout", default])
+                            .on(default)
+                            .undo(Step::git("Going back to", &["checkout", branch]).on(branch)),
+                        Step::git("Pulling changes from", &["pull", remote, default]).on(default),
+                        Step::git("Going back to original branch", &["checkout", branch])
+                            .on(branch),
+                        // Popping the stash into a half rebased tree would only add conflicts
+                        update.on(default).halting(),
+                    ],
+                ))
+            }
+            Action::Commit => {
+                if config.arguments.is_empty() {
+                    return Err(LateremError::InvalidArgument(String::from(
+                        "missing the commit message, pass it with `--args`",
+                    )));
                 }
+
+                let args: Vec<&str> = ["commit", "-m"]
+                    .into_iter()
+                    .chain(config.arguments.iter().map(String::as_str))
+                    .collect();
+
+                Ok(vec![
+                    Step::git("Committing staged changes to", &args).on(branch)
+                ])
             }
+            Action::Push => Ok(vec![Step::git(
+                "Pushing committed changes to",
+                &["push", remote, branch],
+            )
+            .on(branch)]),
+            Action::Pull => Ok(stashed(
+                "*",
+                vec![Step::git("Pulling changes from", &["pull", remote, branch]).on(branch)],
+            )),
+            action => Err(LateremError::InvalidArgument(format!(
+                "`{}` isn't a repository action",
+                action
+            ))),
+        }
+    }
+}
+
+fn loaded(config: &Config) -> Result<&DefaultConfig, LateremError> {
+    config
+        .defaults
+        .as_deref()
+        .ok_or(LateremError::InvalidConfig(String::from(
+            "the config wasn't loaded before running the action",
+        )))
+}
+
+/// `docker compose` followed by the project options shared by every compose invocation
+fn compose(defaults: &DefaultConfig) -> Vec<&str> {
+    let mut compose = vec!["compose"];
+
+    if let Some(directory) = &defaults.working_directory {
+        compose.extend(["--project-directory", directory.as_str()]);
+    }
+
+    for file in &defaults.compose_files {
+        compose.extend(["-f", file.as_str()]);
+    }
+
+    if let Some(name) = &defaults.project_name {
+        compose.extend(["-p", name.as_str()]);
+    }
+
+    for file in &defaults.env_files {
+        compose.extend(["--env-file", file.as_str()]);
+    }
+
+    compose
+}
+
+/// Command `exec` runs when none is given, bash when the image has it and sh otherwise
+const SHELL: [&str; 3] = [
+    "sh",
+    "-c",
+    "if command -v bash > /dev/null; then exec bash; else exec sh; fi",
+];
+
+/// The services given as values or with `--args`, an empty list standing for the whole project
+fn requested(config: &Config) -> Vec<&str> {
+    config
+        .values
+        .iter()
+        .chain(&config.arguments)
+        .map(String::as_str)
+        .collect()
+}
+
+/// Make sure the compose project declares every one of `services`
+fn check_services(
+    runner: &mut dyn CommandRunner,
+    compose: &[&str],
+    services: &[&str],
+) -> Result<(), LateremError> {
+    if services.is_empty() {
+        return Ok(());
+    }
+
+    let declared = runner.capture("docker", &[compose, &["config", "--services"]].concat())?;
+    let declared: Vec<&str> = declared.lines().map(str::trim).collect();
+
+    match services.iter().find(|service| !declared.contains(service)) {
+        Some(unknown) => Err(LateremError::InvalidArgument(format!(
+            "unknown service `{}`, the compose project declares: {}",
+            unknown,
+            declared.join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Named volumes of the compose project, the external ones survive `down --volumes` so they're
+/// left out
+fn volumes(runner: &mut dyn CommandRunner, compose: &[&str]) -> Result<Vec<String>, LateremError> {
+    let query = [compose, &["config", "--format", "json"]].concat();
+    let output = runner.capture("docker", &query)?;
+
+    let model: Value =
+        serde_json::from_str(&output).map_err(|error| LateremError::UnexpectedOutput {
+            command: [&["docker"], &query[..]].concat().join(" "),
+            reason: error.to_string(),
+        })?;
+
+    let Some(volumes) = model["volumes"].as_object() else {
+        return Ok(Vec::new());
+    };
+
+    Ok(volumes
+        .iter()
+        .filter(|(_, volume)| volume["external"] != Value::Bool(true))
+        .map(|(key, volume)| volume["name"].as_str().unwrap_or(key).to_string())
+        .collect())
+}
+
+/// List the `volumes` that `nuke` destroys and ask before going on, `--yes` and dry runs skip
+/// the question
+fn confirm_nuke(
+    config: &Config,
+    runner: &dyn CommandRunner,
+    volumes: &[String],
+) -> Result<(), LateremError> {
+    if volumes.is_empty() {
+        println!(
+            " {}\t\n",
+            "The project has no named volume, only its containers will be removed".dim()
+        );
+    } else {
+        println!(" {}", "Volumes to destroy:".dark_red().bold());
+        for volume in volumes {
+            println!("   - {}", volume.clone().magenta());
         }
+        println!();
+    }
+
+    if config.yes || runner.dry_run() {
+        return Ok(());
+    }
+
+    if !config.interactive || !io::stdin().is_terminal() {
+        return Err(LateremError::InvalidArgument(String::from(
+            "`nuke` destroys the volumes of the project, confirm it with `--yes` without a terminal",
+        )));
+    }
+
+    match wizard::confirm("Destroy them and rebuild the project?", false) {
+        Ok(true) => Ok(()),
+        _ => Err(LateremError::Cancelled),
     }
 }
 
+/// Surround `steps` with staging `pathspec` into a stash named `message` and popping it
+///
+/// The pop looks the entry up by its message and is skipped when there's none, e.g. when
+/// everything was ignored, rather than applying an older stash
+fn stashed(pathspec: &str, message: &str, steps: Vec<Step>) -> Vec<Step> {
+    let pop = || {
+        let message = message.to_string();
+
+        Step::git("Popping stash", &["stash", "pop"]).resolved(move |runner| {
+            let reference = runner.stash_reference(&message)?;
+
+            Ok(reference
+                .map(|reference| vec![String::from("stash"), String::from("pop"), reference]))
+        })
+    };
+
+    let mut all = vec![
+        Step::git("Staging files", &["add", pathspec]),
+        Step::git("Stashing staged files", &["stash", "push", "-m", message]).undo(pop()),
+    ];
+    all.extend(steps);
+    all.push(pop());
+
+    all
+}
+
+/// Replace the failure of a rebase or merge that stopped on conflicts with an explanation of the
+/// state it left, the stash it kept included
+fn conflict(error: LateremError, runner: &mut dyn CommandRunner, stash: &str) -> LateremError {
+    let Ok(status) = runner.status() else {
+        return error;
+    };
+
+    match status.operation {
+        Some(operation) => LateremError::Conflict {
+            operation: operation.to_string(),
+            branch: status.branch,
+            stash: runner.stash_reference(stash).ok().flatten(),
+        },
+        None => error,
+    }
+}
+
+/// Print the checked out branch, whether it has uncommitted changes and how far it is from its
+/// upstream
+fn print_status(status: &Status) {
+    let mut details = Vec::new();
+
+    if status.dirty {
+        details.push(String::from("uncommitted changes"));
+    }
+
+    if let Some(upstream) = &status.upstream {
+        details.push(format!(
+            "{} ahead, {} behind {}",
+            upstream.ahead, upstream.behind, upstream.name
+        ));
+    }
+
+    let details = match details.is_empty() {
+        true => String::new(),
+        false => format!("({})", details.join(", ")),
+    };
+
+    println!(
+        " - {} {} {}\t\n",
+        "Branch:".dark_magenta().bold(),
+        status.branch.clone().magenta(),
+        details.dim(),
+    );
+}
+
 impl Config {
-    pub fn setup(&mut self) {
-        // TODO: prompt user to create first config file
-        // let config = std::fs::read(&self.path);
+    /// Layer the global config at `path`, the closest `.laterem.json`, the selected profile of
+    /// each, the `LATEREM_<FIELD>` variables and the flags over the built-in defaults, detecting
+    /// every field none of them set
+    pub fn load(&mut self) -> Result<(), LateremError> {
+        self.read_layers(!self.dry_run)
+    }
+
+    /// Like [`Config::load`] without writing anything nor printing a line, for the actions that
+    /// only read the config: a missing global file is empty and outdated files aren't upgraded
+    pub fn load_read_only(&mut self) -> Result<(), LateremError> {
+        self.read_layers(false)
+    }
+
+    /// Load every layer, creating the missing global config and upgrading the outdated files
+    /// only with `write`
+    fn read_layers(&mut self, write: bool) -> Result<(), LateremError> {
+        let origin = Origin::Global(self.path.clone());
+        let global = match Layer::read(&self.path, origin.clone(), write)? {
+            Some(layer) => layer,
+            None if !write => {
+                if self.dry_run {
+                    println!(
+                        " - {} {}\t\n",
+                        "Config not created, planning with the defaults:"
+                            .dark_magenta()
+                            .bold(),
+                        self.path.display(),
+                    );
+                }
+
+                Layer::new(origin, Map::new())
+            }
+            None => Layer::new(origin, wizard::create(&self.path, self.interactive)?),
+        };
+
+        let mut files = vec![global];
+
+        let repository = env::current_dir()
+            .ok()
+            .and_then(|current| layers::repository_file(&current));
+        if let Some(path) = repository {
+            files.extend(Layer::read(&path, Origin::Repository(path.clone()), write)?);
+        }
+
+        if self.dry_run {
+            files.iter().for_each(Layer::warn_outdated);
+        }
+
+        let mut layers = layers::select_profile(files, self.profile.clone())?;
+        layers.extend(Layer::environment());
+
+        if !self.overrides.is_empty() {
+            layers.push(Layer::new(Origin::CommandLine, self.overrides.clone()));
+        }
+
+        let (defaults, origins) = layers::resolve(&layers)?;
 
-        let defaults = DefaultConfig::default();
         self.defaults = Some(Box::new(defaults));
+        self.origins = origins;
 
-        let response = Action::run(self);
+        if self.show_origin && !matches!(self.target, Target::Config) {
+            println!(
+                "{}\t\n",
+                " EFFECTIVE CONFIG ".bold().on_dark_magenta().white()
+            );
+            self.print_values();
+            println!();
+        }
+
+        Ok(())
+    }
+
+    /// Print every effective config value, alongside the layer that set it with `show_origin`
+    pub fn print_values(&self) {
+        let Some(defaults) = &self.defaults else {
+            return;
+        };
+
+        for (key, value) in defaults.values() {
+            let origin = match self.origins.get(&key) {
+                Some(origin) if self.show_origin => format!("({})", origin),
+                _ => String::new(),
+            };
+
+            println!(
+                " - {} {} {}",
+                format!("{}:", key).dark_magenta().bold(),
+                value,
+                origin.dim(),
+            );
+        }
+    }
+
+    pub fn setup(&mut self) -> ExitCode {
+        let response = match self.target {
+            Target::Config => settings::run(self),
+            _ if self.dry_run => self
+                .load()
+                .and_then(|_| Action::run(self, &mut DryRunRunner)),
+            _ => self
+                .load()
+                .and_then(|_| Action::run(self, &mut SystemRunner)),
+        };
 
         match response {
+            Ok(()) if matches!(self.target, Target::Config) => ExitCode::SUCCESS,
+            Ok(()) if self.dry_run => {
+                println!(
+                    "\t\n{}\t\n",
+                    " DRY RUN, NOTHING WAS CHANGED "
+                        .on_dark_magenta()
+                        .white()
+                        .bold(),
+                );
+
+                ExitCode::SUCCESS
+            }
             Ok(()) => {
                 println!(
                     "\t\n{}\t\n",
@@ -572,7 +1125,11 @@ impl Config {
                         .white()
                         .bold(),
                 );
+
+                ExitCode::SUCCESS
             }
+            // The command already spoke for itself, e.g. the last status of a shell
+            Err(LateremError::Exited { code, .. }) => ExitCode::from(code),
             Err(message) => {
                 println!("\t\n{}\t\n", " ERROR OUTPUT ".on_dark_red().white().bold(),);
 
@@ -581,7 +1138,603 @@ impl Config {
                     "An error ocurred:".slow_blink().bold(),
                     message.to_string().slow_blink().underlined(),
                 );
+                println!(" {} {}\t\n", "Hint:".bold(), message.hint().dim());
+
+                ExitCode::from(message.exit_code())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::runner::RecordingRunner;
+
+    const STASH: &str = "git stash push -m laterem:test";
+    const POP: &str = "git stash pop stash@{1}";
+
+    fn defaults() -> DefaultConfig {
+        DefaultConfig {
+            branch: String::from("main"),
+            remote: String::from("origin"),
+            stash_files: true,
+            detach_container: true,
+            compose_files: Vec::new(),
+            project_name: None,
+            env_files: Vec::new(),
+            working_directory: None,
+            sync_strategy: SyncStrategy::Rebase,
+            wait_timeout: 60,
+            wait_ports: BTreeMap::new(),
+        }
+    }
+
+    fn config(target: Target, action: Action, defaults: DefaultConfig) -> Config {
+        Config {
+            defaults: Some(Box::new(defaults)),
+            ..Config::for_test(target, action)
+        }
+    }
+
+    fn run(config: &Config, runner: RecordingRunner) -> (Result<(), LateremError>, Vec<String>) {
+        let mut runner = runner
+            .branch("feature")
+            .stash("laterem:test")
+            .stash("laterem:other");
+        let result = Action::plan(config, &mut runner, "laterem:test")
+            .and_then(|steps| pipeline::run(&steps, &mut runner));
+
+        (result, runner.calls)
+    }
+
+    fn assert_calls(target: Target, action: Action, expected: &[&str]) {
+        let (result, calls) = run(&config(target, action, defaults()), RecordingRunner::new());
+
+        assert!(result.is_ok());
+        assert_eq!(calls, expected);
+    }
+
+    fn assert_invalid(target: Target, action: Action, expected: &[&str]) {
+        let (result, calls) = run(&config(target, action, defaults()), RecordingRunner::new());
+
+        assert!(matches!(result, Err(LateremError::InvalidArgument(_))));
+        assert_eq!(calls, expected);
+    }
+
+    #[test]
+    fn docker_reset() {
+        assert_calls(
+            Target::Docker,
+            Action::Reset,
+            &["docker compose down", "docker compose up -d"],
+        );
+    }
+
+    #[test]
+    fn docker_down() {
+        assert_calls(Target::Docker, Action::Down, &["docker compose down"]);
+    }
+
+    #[test]
+    fn docker_up() {
+        assert_calls(Target::Docker, Action::Up, &["docker compose up -d"]);
+    }
+
+    #[test]
+    fn docker_rejects_repository_actions() {
+        for action in [Action::Commit, Action::Push, Action::Pull] {
+            assert_invalid(Target::Docker, action, &[]);
+        }
+    }
+
+    #[test]
+    fn docker_uses_the_compose_file_and_attached_mode() {
+        let mut defaults = defaults();
+        defaults.compose_files = vec![String::from("docker-compose.dev.yml")];
+        defaults.detach_container = false;
+
+        let (result, calls) = run(
+            &config(Target::Docker, Action::Reset, defaults),
+            RecordingRunner::new(),
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(
+            calls,
+            [
+                "docker compose -f docker-compose.dev.yml down",
+                "docker compose -f docker-compose.dev.yml up",
+            ]
+        );
+    }
+
+    #[test]
+    fn docker_passes_the_project_options_to_every_command() {
+        let mut defaults = defaults();
+        defaults.compose_files = vec![String::from("compose.yml"), String::from("compose.dev.yml")];
+        defaults.project_name = Some(String::from("api"));
+        defaults.env_files = vec![String::from(".env.dev")];
+        defaults.working_directory = Some(String::from("services/api"));
+
+        let (result, calls) = run(
+            &config(Target::Docker, Action::Reset, defaults),
+            RecordingRunner::new(),
+        );
+        let project = "docker compose --project-directory services/api -f compose.yml \
+                       -f compose.dev.yml -p api --env-file .env.dev";
+
+        assert!(result.is_ok());
+        assert_eq!(
+            calls,
+            [format!("{} down", project), format!("{} up -d", project)]
+        );
+    }
+
+    #[test]
+    fn docker_scopes_the_action_to_the_given_services() {
+        let mut config = config(Target::Docker, Action::Reset, defaults());
+        config.values = vec![String::from("api")];
+        config.arguments = vec![String::from("worker")];
+
+        let runner = RecordingRunner::new()
+            .output("docker compose config --services", "api\ndatabase\nworker");
+        let (result, calls) = run(&config, runner);
+
+        assert!(result.is_ok());
+        assert_eq!(
+            calls,
+            [
+                "docker compose config --services",
+                "docker compose down api worker",
+                "docker compose up -d api worker",
+            ]
+        );
+    }
+
+    #[test]
+    fn docker_rejects_unknown_services_before_running_anything() {
+        let mut config = config(Target::Docker, Action::Up, defaults());
+        config.values = vec![String::from("apu")];
+
+        let runner = RecordingRunner::new().output("docker compose config --services", "api");
+        let (result, calls) = run(&config, runner);
+
+        let error = result.unwrap_err();
+        assert_eq!(error.exit_code(), 2);
+        assert!(error.to_string().contains("declares: api"));
+        assert_eq!(calls, ["docker compose config --services"]);
+    }
+
+    #[test]
+    fn docker_logs_follow_the_given_services() {
+        let mut config = config(Target::Docker, Action::Logs, defaults());
+        config.values = vec![String::from("api")];
+        config.follow = true;
+        config.since = Some(String::from("10m"));
+
+        let runner = RecordingRunner::new().output("docker compose config --services", "api");
+        let (result, calls) = run(&config, runner);
+
+        assert!(result.is_ok());
+        assert_eq!(calls[1..], ["docker compose logs --follow --since 10m api"]);
+    }
+
+    #[test]
+    fn docker_exec_opens_a_shell_by_default() {
+        let services = "api\nworker";
+        let mut shell = config(Target::Docker, Action::Exec, defaults());
+        shell.values = vec![String::from("api")];
+        let mut command = config(Target::Docker, Action::Exec, defaults());
+        command.values = ["worker", "ls", "-la"].map(String::from).to_vec();
+
+        let runner = || RecordingRunner::new().output("docker compose config --services", services);
+        let (_, shell) = run(&shell, runner());
+        let (_, command) = run(&command, runner());
+
+        assert_eq!(
+            shell[1],
+            "docker compose exec api sh -c if command -v bash > /dev/null; then exec bash; else exec sh; fi"
+        );
+        assert_eq!(command[1], "docker compose exec worker ls -la");
+    }
+
+    #[test]
+    fn shells_and_followed_logs_are_attached_to_the_terminal() {
+        let mut exec = config(Target::Docker, Action::Exec, defaults());
+        exec.values = vec![String::from("api")];
+        let mut follow = config(Target::Docker, Action::Logs, defaults());
+        follow.follow = true;
+        let logs = config(Target::Docker, Action::Logs, defaults());
+
+        let attached = |config: &Config| {
+            let mut runner =
+                RecordingRunner::new().output("docker compose config --services", "api");
+            let steps = Action::plan(config, &mut runner, "laterem:test").unwrap();
+
+            steps.iter().all(|step| step.interactive)
+        };
+
+        assert!(attached(&exec));
+        assert!(attached(&follow));
+        assert!(!attached(&logs));
+    }
+
+    #[test]
+    fn docker_exec_needs_a_service() {
+        assert_invalid(Target::Docker, Action::Exec, &[]);
+    }
+
+    const MODEL: &str = r#"{"name":"api","volumes":{
+        "cache":{"name":"api_cache"},
+        "pgdata":{"name":"api_pgdata"},
+        "shared":{"name":"shared","external":true}}}"#;
+
+    #[test]
+    fn docker_nuke_removes_the_volumes_then_rebuilds() {
+        let mut config = config(Target::Docker, Action::Nuke, defaults());
+        config.yes = true;
+        config.no_cache = true;
+
+        let runner = RecordingRunner::new().output("docker compose config --format json", MODEL);
+        let (result, calls) = run(&config, runner);
+
+        assert!(result.is_ok());
+        assert_eq!(
+            calls,
+            [
+                "docker compose config --format json",
+                "docker compose down --volumes --remove-orphans",
+                "docker compose build --no-cache",
+                "docker compose up -d",
+            ]
+        );
+    }
+
+    #[test]
+    fn docker_nuke_lists_every_volume_but_the_external_ones() {
+        let mut runner =
+            RecordingRunner::new().output("docker compose config --format json", MODEL);
+
+        assert_eq!(
+            volumes(&mut runner, &["compose"]).unwrap(),
+            ["api_cache", "api_pgdata"]
+        );
+    }
+
+    #[test]
+    fn docker_nuke_needs_a_confirmation_without_a_terminal() {
+        let runner = RecordingRunner::new().output("docker compose config --format json", MODEL);
+        let (result, calls) = run(&config(Target::Docker, Action::Nuke, defaults()), runner);
+
+        assert!(matches!(result, Err(LateremError::InvalidArgument(_))));
+        assert_eq!(calls, ["docker compose config --format json"]);
+    }
+
+    #[test]
+    fn docker_up_waits_for_the_started_services() {
+        let mut config = config(Target::Docker, Action::Up, defaults());
+        config.values = vec![String::from("api")];
+        let mut runner = RecordingRunner::new().output(
+            "docker compose ps --all --format json api",
+            "{\"Service\":\"api\",\"State\":\"running\",\"Health\":\"healthy\"}",
+        );
+
+        assert!(Action::Up.wait(&config, &mut runner).is_ok());
+        assert_eq!(runner.calls, ["docker compose ps --all --format json api"]);
+    }
+
+    #[test]
+    fn docker_skips_the_wait_when_attached_or_disabled() {
+        let mut attached = defaults();
+        attached.detach_container = false;
+        let mut disabled = defaults();
+        disabled.wait_timeout = 0;
+
+        for defaults in [attached, disabled] {
+            let config = config(Target::Docker, Action::Reset, defaults);
+            let mut runner = RecordingRunner::new();
+
+            assert!(Action::Reset.wait(&config, &mut runner).is_ok());
+            assert!(runner.calls.is_empty());
+        }
+    }
+
+    #[test]
+    fn compose_files_accept_a_single_file() {
+        let values = Map::from_iter([(
+            String::from("compose_files"),
+            Value::from("compose.dev.yml"),
+        )]);
+
+        let defaults = DefaultConfig::check(&values).unwrap();
+
+        assert_eq!(defaults.compose_files, ["compose.dev.yml"]);
+    }
+
+    #[test]
+    fn repository_reset() {
+        assert_calls(
+            Target::Repository,
+            Action::Reset,
+            &[
+                "git add .",
+                STASH,
+                "git checkout main",
+                "git pull origin main",
+                "git checkout feature",
+                POP,
+            ],
+        );
+    }
+
+    #[test]
+    fn repository_reset_without_stashing() {
+        let mut defaults = defaults();
+        defaults.stash_files = false;
+
+        let (result, calls) = run(
+            &config(Target::Repository, Action::Reset, defaults),
+            RecordingRunner::new(),
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(
+            calls,
+            [
+                "git checkout main",
+                "git pull origin main",
+                "git checkout feature",
+            ]
+        );
+    }
+
+    #[test]
+    fn repository_reset_on_a_clean_tree_skips_stashing() {
+        let (result, calls) = run(
+            &config(Target::Repository, Action::Reset, defaults()),
+            RecordingRunner::new().clean(),
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(
+            calls,
+            [
+                "git checkout main",
+                "git pull origin main",
+                "git checkout feature",
+            ]
+        );
+    }
+
+    #[test]
+    fn repository_reset_without_a_created_stash_pops_nothing() {
+        let mut runner = RecordingRunner::new().branch("feature").stash("other");
+        let config = config(Target::Repository, Action::Reset, defaults());
+
+        let result = Action::plan(&config, &mut runner, "laterem:test")
+            .and_then(|steps| pipeline::run(&steps, &mut runner));
+
+        assert!(result.is_ok());
+        assert_eq!(
+            runner.calls,
+            [
+                "git add .",
+                STASH,
+                "git checkout main",
+                "git pull origin main",
+                "git checkout feature",
+            ]
+        );
+    }
+
+    #[test]
+    fn repository_sync_rebases_onto_the_updated_default_branch() {
+        assert_calls(
+            Target::Repository,
+            Action::Sync,
+            &[
+                "git add .",
+                STASH,
+                "git checkout main",
+                "git pull origin main",
+                "git checkout feature",
+                "git rebase main",
+                POP,
+            ],
+        );
+    }
+
+    #[test]
+    fn repository_sync_merges_per_config() {
+        let mut defaults = defaults();
+        defaults.sync_strategy = SyncStrategy::Merge;
+
+        let (result, calls) = run(
+            &config(Target::Repository, Action::Sync, defaults),
+            RecordingRunner::new().clean(),
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(
+            calls,
+            [
+                "git checkout main",
+                "git pull origin main",
+                "git checkout feature",
+                "git merge main",
+            ]
+        );
+    }
+
+    #[test]
+    fn repository_sync_stops_on_conflicts_and_keeps_the_stash() {
+        let config = config(Target::Repository, Action::Sync, defaults());
+        let mut runner = RecordingRunner::new()
+            .branch("feature")
+            .stash("laterem:test")
+            .conflicting("git rebase main", "rebase");
+
+        let error = Action::plan(&config, &mut runner, "laterem:test")
+            .and_then(|steps| pipeline::run(&steps, &mut runner))
+            .map_err(|error| conflict(error, &mut runner, "laterem:test"))
+            .unwrap_err();
+
+        assert_eq!(runner.calls.last().unwrap(), "git rebase main");
+        assert!(!runner
+            .calls
+            .iter()
+            .any(|call| call.starts_with("git stash pop")));
+        assert!(error.hint().contains("git rebase --continue"));
+        assert!(error.hint().contains("git stash pop stash@{0}"));
+        assert_eq!(error.exit_code(), 4);
+    }
+
+    #[test]
+    fn repository_commit() {
+        let mut config = config(Target::Repository, Action::Commit, defaults());
+        config.arguments = vec![String::from("fix: bug fix")];
+
+        let (result, calls) = run(&config, RecordingRunner::new());
+
+        assert!(result.is_ok());
+        assert_eq!(calls, ["git commit -m fix: bug fix"]);
+    }
+
+    #[test]
+    fn repository_commit_requires_a_message() {
+        assert_invalid(Target::Repository, Action::Commit, &[]);
+    }
+
+    #[test]
+    fn repository_push() {
+        assert_calls(
+            Target::Repository,
+            Action::Push,
+            &["git push origin feature"],
+        );
+    }
+
+    #[test]
+    fn repository_pull() {
+        assert_calls(
+            Target::Repository,
+            Action::Pull,
+            &["git add *", STASH, "git pull origin feature", POP],
+        );
+    }
+
+    #[test]
+    fn repository_rejects_docker_actions() {
+        for action in [Action::Down, Action::Up] {
+            assert_invalid(Target::Repository, action, &[]);
+        }
+    }
+
+    #[test]
+    fn repository_requires_a_default_branch() {
+        let mut defaults = defaults();
+        defaults.branch = String::new();
+
+        let (result, calls) = run(
+            &config(Target::Repository, Action::Reset, defaults),
+            RecordingRunner::new(),
+        );
+
+        assert!(matches!(result, Err(LateremError::MissingDefaultBranch)));
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn repository_failure_rolls_back() {
+        let (result, calls) = run(
+            &config(Target::Repository, Action::Reset, defaults()),
+            RecordingRunner::new().failing("git pull origin main"),
+        );
+
+        assert_eq!(result.unwrap_err().exit_code(), 4);
+        assert_eq!(
+            calls,
+            [
+                "git add .",
+                STASH,
+                "git checkout main",
+                "git pull origin main",
+                "git checkout feature",
+                POP,
+            ]
+        );
+    }
+
+    #[test]
+    fn current_reset() {
+        assert_calls(
+            Target::RepositoryCurrent,
+            Action::Reset,
+            &[
+                "git add .",
+                STASH,
+                "git fetch origin feature",
+                "git pull origin feature",
+                POP,
+            ],
+        );
+    }
+
+    #[test]
+    fn current_commit() {
+        let mut config = config(Target::RepositoryCurrent, Action::Commit, defaults());
+        config.arguments = vec![String::from("wip")];
+
+        let (result, calls) = run(&config, RecordingRunner::new());
+
+        assert!(result.is_ok());
+        assert_eq!(calls, ["git commit -m wip"]);
+    }
+
+    #[test]
+    fn current_push() {
+        assert_calls(
+            Target::RepositoryCurrent,
+            Action::Push,
+            &["git push origin feature"],
+        );
+    }
+
+    #[test]
+    fn current_pull() {
+        assert_calls(
+            Target::RepositoryCurrent,
+            Action::Pull,
+            &["git add *", STASH, "git pull origin feature", POP],
+        );
+    }
+
+    #[test]
+    fn current_rejects_docker_actions() {
+        for action in [Action::Down, Action::Up] {
+            assert_invalid(Target::RepositoryCurrent, action, &[]);
+        }
+    }
+
+    #[test]
+    fn config_actions_never_run_commands() {
+        for action in [
+            Action::Get,
+            Action::Set,
+            Action::Unset,
+            Action::List,
+            Action::Edit,
+            Action::Validate,
+        ] {
+            assert_invalid(Target::Config, action, &[]);
+        }
+    }
+
+    #[test]
+    fn config_actions_are_rejected_by_other_targets() {
+        assert_invalid(Target::Docker, Action::List, &[]);
+        assert_invalid(Target::Repository, Action::Set, &[]);
+        assert_invalid(Target::RepositoryCurrent, Action::Get, &[]);
+    }
+}
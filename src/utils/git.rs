@@ -0,0 +1,440 @@
+use git2::{BranchType, ConfigLevel, ErrorCode, Repository, RepositoryState, StatusOptions};
+
+use super::entities::LateremError;
+
+/// Local git config key remembering the default branch found without `origin/HEAD`
+const CACHE_KEY: &str = "laterem.defaultBranch";
+
+/// Branches tried after `init.defaultBranch`, in order
+const CANDIDATES: [&str; 3] = ["main", "master", "develop"];
+
+/// State of the checked out branch, read without spawning git
+pub struct Status {
+    pub branch: String,
+    /// whether there are staged, unstaged or untracked changes
+    pub dirty: bool,
+    pub upstream: Option<Upstream>,
+    /// `rebase` or `merge` when one was stopped, e.g. on conflicts
+    pub operation: Option<&'static str>,
+}
+
+/// Distance between a branch and the one it tracks
+pub struct Upstream {
+    pub name: String,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+/// Open the repository containing the current directory, following the same environment
+/// variables as git, e.g. `GIT_DIR` and `GIT_CEILING_DIRECTORIES`
+pub fn open() -> Result<Repository, LateremError> {
+    Repository::open_from_env().map_err(|error| match error.code() {
+        ErrorCode::NotFound => LateremError::NotGitRepository,
+        _ => LateremError::Git(error),
+    })
+}
+
+pub fn status(repository: &Repository) -> Result<Status, LateremError> {
+    let branch = current_branch(repository)?;
+
+    Ok(Status {
+        dirty: is_dirty(repository)?,
+        upstream: upstream(repository, &branch)?,
+        operation: operation(repository),
+        branch,
+    })
+}
+
+/// The rebase or merge waiting for the user to continue or abort it
+pub fn operation(repository: &Repository) -> Option<&'static str> {
+    match repository.state() {
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge
+        | RepositoryState::ApplyMailboxOrRebase => Some("rebase"),
+        RepositoryState::Merge => Some("merge"),
+        _ => None,
+    }
+}
+
+/// Name of the checked out branch, even when it has no commits yet or while a rebase detached
+/// `HEAD`
+pub fn current_branch(repository: &Repository) -> Result<String, LateremError> {
+    let head = repository.find_reference("HEAD")?;
+    let rebasing = ["rebase-merge/head-name", "rebase-apply/head-name"]
+        .iter()
+        .find_map(|file| std::fs::read_to_string(repository.path().join(file)).ok());
+
+    head.symbolic_target()
+        .map(String::from)
+        .or(rebasing)
+        .and_then(|target| target.trim().strip_prefix("refs/heads/").map(String::from))
+        .ok_or_else(|| {
+            LateremError::Git(git2::Error::from_str(
+                "HEAD is detached, check out a branch first",
+            ))
+        })
+}
+
+/// Branch `refs/remotes/<remote>/HEAD` points to, as set by `git clone` or
+/// `git remote set-head`
+pub fn default_branch(repository: &Repository, remote: &str) -> Option<String> {
+    let prefix = format!("refs/remotes/{}/", remote);
+    let head = repository.find_reference(&format!("{}HEAD", prefix)).ok()?;
+
+    head.symbolic_target()?
+        .strip_prefix(&prefix)
+        .map(String::from)
+}
+
+/// Find the default branch without touching the network: `refs/remotes/<remote>/HEAD`, then the
+/// branch cached by an earlier run, then the first existing branch among `init.defaultBranch`,
+/// `main`, `master` and `develop`
+///
+/// Detection only reads, [`cache_default_branch`] keeps the guess
+pub fn detect_default_branch(repository: &Repository, remote: &str) -> Option<String> {
+    if let Some(branch) = default_branch(repository, remote) {
+        return Some(branch);
+    }
+
+    let config = repository.config().ok();
+    let get = |key: &str| config.as_ref()?.get_string(key).ok();
+
+    if let Some(branch) = get(CACHE_KEY).filter(|branch| exists(repository, remote, branch)) {
+        return Some(branch);
+    }
+
+    get("init.defaultBranch")
+        .into_iter()
+        .chain(CANDIDATES.map(String::from))
+        .find(|branch| exists(repository, remote, branch))
+}
+
+/// Cache a guessed default branch in the local git config so it doesn't change once e.g. a
+/// `develop` branch is created, `git config --unset laterem.defaultBranch` clears it
+///
+/// Nothing is written when `refs/remotes/<remote>/HEAD` is set or the cache already holds `branch`
+pub fn cache_default_branch(repository: &Repository, remote: &str, branch: &str) {
+    if default_branch(repository, remote).is_some() {
+        return;
+    }
+
+    let Ok(config) = repository.config() else {
+        return;
+    };
+    if config.get_string(CACHE_KEY).ok().as_deref() == Some(branch) {
+        return;
+    }
+
+    // A read-only repository only loses the cache
+    let _ = config
+        .open_level(ConfigLevel::Local)
+        .and_then(|mut local| local.set_str(CACHE_KEY, branch));
+}
+
+/// Whether `branch` exists locally or on `remote`
+fn exists(repository: &Repository, remote: &str, branch: &str) -> bool {
+    [
+        format!("refs/heads/{}", branch),
+        format!("refs/remotes/{}/{}", remote, branch),
+    ]
+    .iter()
+    .any(|name| repository.find_reference(name).is_ok())
+}
+
+pub fn is_dirty(repository: &Repository) -> Result<bool, LateremError> {
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).exclude_submodules(true);
+
+    Ok(!repository.statuses(Some(&mut options))?.is_empty())
+}
+
+/// Reference of the newest stash entry whose message is `message`, e.g. `stash@{1}`
+pub fn stash_reference(
+    repository: &mut Repository,
+    message: &str,
+) -> Result<Option<String>, LateremError> {
+    let suffix = format!(": {}", message);
+    let mut reference = None;
+
+    repository.stash_foreach(|index, entry, _| {
+        if entry.ends_with(&suffix) {
+            reference = Some(format!("stash@{{{}}}", index));
+        }
+
+        reference.is_none()
+    })?;
+
+    Ok(reference)
+}
+
+/// Commits `branch` is ahead and behind the branch it tracks, `None` without one
+pub fn upstream(repository: &Repository, branch: &str) -> Result<Option<Upstream>, LateremError> {
+    let local = match repository.find_branch(branch, BranchType::Local) {
+        Ok(local) => local,
+        Err(error) if error.code() == ErrorCode::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    let upstream = match local.upstream() {
+        Ok(upstream) => upstream,
+        Err(error) if error.code() == ErrorCode::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    let (Some(local_id), Some(upstream_id)) = (local.get().target(), upstream.get().target())
+    else {
+        return Ok(None);
+    };
+    let (ahead, behind) = repository.graph_ahead_behind(local_id, upstream_id)?;
+
+    Ok(Some(Upstream {
+        name: upstream.name()?.unwrap_or_default().to_string(),
+        ahead,
+        behind,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, process::Command};
+
+    use super::*;
+
+    fn git(directory: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args([
+                "-c",
+                "user.name=laterem",
+                "-c",
+                "user.email=laterem@localhost",
+            ])
+            .args(args)
+            .current_dir(directory)
+            .output()
+            .unwrap()
+            .status;
+
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    /// A clone of a repository with a single commit on `main`
+    fn clone() -> (tempfile::TempDir, Repository) {
+        let directory = tempfile::tempdir().unwrap();
+        let origin = directory.path().join("origin");
+        let work = directory.path().join("work");
+
+        fs::create_dir(&origin).unwrap();
+        git(&origin, &["init", "-b", "main"]);
+        git(&origin, &["commit", "--allow-empty", "-m", "initial"]);
+        git(directory.path(), &["clone", "-q", "origin", "work"]);
+
+        let repository = Repository::open(&work).unwrap();
+
+        (directory, repository)
+    }
+
+    #[test]
+    fn reads_the_current_and_default_branch() {
+        let (directory, repository) = clone();
+        git(
+            &directory.path().join("work"),
+            &["checkout", "-b", "feature"],
+        );
+
+        assert_eq!(current_branch(&repository).unwrap(), "feature");
+        assert_eq!(default_branch(&repository, "origin").unwrap(), "main");
+        assert!(default_branch(&repository, "upstream").is_none());
+    }
+
+    #[test]
+    fn default_branch_falls_back_to_existing_candidates() {
+        let (directory, repository) = clone();
+        let work = directory.path().join("work");
+        git(&work, &["remote", "set-head", "origin", "--delete"]);
+        git(&work, &["branch", "-m", "main", "develop"]);
+
+        assert_eq!(
+            detect_default_branch(&repository, "origin").unwrap(),
+            "main"
+        );
+
+        git(&work, &["branch", "-r", "-d", "origin/main"]);
+
+        assert_eq!(
+            detect_default_branch(&repository, "origin").unwrap(),
+            "develop"
+        );
+    }
+
+    #[test]
+    fn init_default_branch_is_tried_first() {
+        let (directory, repository) = clone();
+        let work = directory.path().join("work");
+        git(&work, &["remote", "set-head", "origin", "--delete"]);
+        git(&work, &["branch", "trunk"]);
+        git(&work, &["config", "init.defaultBranch", "trunk"]);
+
+        assert_eq!(
+            detect_default_branch(&repository, "origin").unwrap(),
+            "trunk"
+        );
+    }
+
+    #[test]
+    fn guessed_default_branch_is_cached() {
+        let (directory, repository) = clone();
+        let work = directory.path().join("work");
+        git(&work, &["remote", "set-head", "origin", "--delete"]);
+        git(&work, &["branch", "-m", "main", "develop"]);
+        git(&work, &["branch", "-r", "-d", "origin/main"]);
+
+        assert_eq!(
+            detect_default_branch(&repository, "origin").unwrap(),
+            "develop"
+        );
+        cache_default_branch(&repository, "origin", "develop");
+
+        git(&work, &["branch", "master"]);
+
+        assert_eq!(
+            detect_default_branch(&repository, "origin").unwrap(),
+            "develop"
+        );
+
+        git(
+            &work,
+            &[
+                "symbolic-ref",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/master",
+            ],
+        );
+
+        assert_eq!(
+            detect_default_branch(&repository, "origin").unwrap(),
+            "master"
+        );
+    }
+
+    #[test]
+    fn detection_leaves_the_git_config_alone() {
+        let (directory, repository) = clone();
+        let work = directory.path().join("work");
+        git(&work, &["remote", "set-head", "origin", "--delete"]);
+
+        assert_eq!(
+            detect_default_branch(&repository, "origin").unwrap(),
+            "main"
+        );
+        assert!(repository.config().unwrap().get_string(CACHE_KEY).is_err());
+    }
+
+    #[test]
+    fn missing_default_branch_is_none() {
+        let (directory, repository) = clone();
+        let work = directory.path().join("work");
+        git(&work, &["remote", "set-head", "origin", "--delete"]);
+        git(&work, &["branch", "-m", "main", "feature"]);
+        git(&work, &["branch", "-r", "-d", "origin/main"]);
+
+        assert!(detect_default_branch(&repository, "origin").is_none());
+    }
+
+    #[test]
+    fn unborn_branch_has_a_name() {
+        let directory = tempfile::tempdir().unwrap();
+        git(directory.path(), &["init", "-b", "trunk"]);
+
+        let repository = Repository::open(directory.path()).unwrap();
+
+        assert_eq!(current_branch(&repository).unwrap(), "trunk");
+    }
+
+    #[test]
+    fn detached_head_is_an_error() {
+        let (directory, repository) = clone();
+        git(&directory.path().join("work"), &["checkout", "--detach"]);
+
+        assert!(matches!(
+            current_branch(&repository),
+            Err(LateremError::Git(_))
+        ));
+    }
+
+    #[test]
+    fn stopped_rebase_is_reported() {
+        let (directory, repository) = clone();
+        let work = directory.path().join("work");
+
+        fs::write(work.join("file.txt"), "main").unwrap();
+        git(&work, &["add", "."]);
+        git(&work, &["commit", "-m", "main"]);
+        git(&work, &["checkout", "-q", "-b", "feature", "HEAD~1"]);
+        fs::write(work.join("file.txt"), "feature").unwrap();
+        git(&work, &["add", "."]);
+        git(&work, &["commit", "-m", "feature"]);
+
+        assert_eq!(operation(&repository), None);
+
+        let rebase = Command::new("git")
+            .args(["rebase", "main"])
+            .current_dir(&work)
+            .output()
+            .unwrap();
+
+        assert!(!rebase.status.success());
+        assert_eq!(operation(&repository), Some("rebase"));
+        assert_eq!(current_branch(&repository).unwrap(), "feature");
+    }
+
+    #[test]
+    fn untracked_files_make_the_tree_dirty() {
+        let (directory, repository) = clone();
+
+        assert!(!is_dirty(&repository).unwrap());
+
+        fs::write(directory.path().join("work/new.txt"), "new").unwrap();
+
+        assert!(is_dirty(&repository).unwrap());
+    }
+
+    #[test]
+    fn finds_a_stash_by_message() {
+        let (directory, mut repository) = clone();
+        let work = directory.path().join("work");
+
+        for message in ["laterem:reset:1", "other"] {
+            fs::write(work.join("new.txt"), message).unwrap();
+            git(&work, &["stash", "push", "-u", "-m", message]);
+        }
+
+        assert_eq!(
+            stash_reference(&mut repository, "laterem:reset:1").unwrap(),
+            Some(String::from("stash@{1}"))
+        );
+        assert_eq!(
+            stash_reference(&mut repository, "laterem:reset:2").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn counts_commits_ahead_and_behind() {
+        let (directory, repository) = clone();
+        let work = directory.path().join("work");
+
+        git(
+            &directory.path().join("origin"),
+            &["commit", "--allow-empty", "-m", "theirs"],
+        );
+        git(&work, &["fetch", "-q"]);
+        git(&work, &["commit", "--allow-empty", "-m", "ours"]);
+
+        let upstream = upstream(&repository, "main").unwrap().unwrap();
+
+        assert_eq!(upstream.name, "origin/main");
+        assert_eq!((upstream.ahead, upstream.behind), (1, 1));
+        assert!(self::upstream(&repository, "missing").unwrap().is_none());
+    }
+}
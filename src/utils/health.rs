@@ -0,0 +1,493 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, IsTerminal, Write},
+    net::{Ipv4Addr, SocketAddr, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor::MoveUp,
+    style::Stylize,
+    terminal::{Clear, ClearType},
+    QueueableCommand,
+};
+use serde::Deserialize;
+
+use super::{entities::LateremError, runner::CommandRunner};
+
+/// Time between two polls of the containers
+const POLL: Duration = Duration::from_millis(500);
+
+/// How long a port gets to accept a connection on each poll
+const CONNECT: Duration = Duration::from_millis(200);
+
+/// Log lines printed for the services that didn't become healthy
+const LOG_LINES: &str = "20";
+
+/// A container as listed by `docker compose ps --format json`
+#[derive(Deserialize)]
+pub struct Container {
+    #[serde(rename = "Service")]
+    pub service: String,
+    #[serde(rename = "State", default)]
+    pub state: String,
+    #[serde(rename = "Health", default)]
+    pub health: String,
+    #[serde(rename = "ExitCode", default)]
+    pub exit_code: i64,
+    #[serde(rename = "Publishers", default)]
+    pub publishers: Vec<Publisher>,
+}
+
+/// A container port, `published_port` being 0 when it's only exposed to the other services
+#[derive(Deserialize)]
+pub struct Publisher {
+    #[serde(rename = "TargetPort", default)]
+    pub target_port: u16,
+    #[serde(rename = "PublishedPort", default)]
+    pub published_port: u16,
+    #[serde(rename = "Protocol", default)]
+    pub protocol: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Readiness {
+    Ready,
+    Waiting,
+    /// the container stopped, waiting longer won't help
+    Failed,
+}
+
+/// A line of the status table
+struct Row {
+    service: String,
+    state: String,
+    health: String,
+    /// published ports, e.g: `5432->5432/tcp`
+    published: Vec<String>,
+    /// the port declared in `wait_ports` and whether it accepts connections
+    port: Option<(u16, bool)>,
+    readiness: Readiness,
+}
+
+impl Row {
+    fn new(container: &Container, ports: &BTreeMap<String, u16>) -> Self {
+        let port = ports
+            .get(&container.service)
+            .map(|port| (*port, accepts(*port)));
+
+        let mut published: Vec<String> = container
+            .publishers
+            .iter()
+            .filter(|publisher| publisher.published_port != 0)
+            .map(|publisher| {
+                format!(
+                    "{}->{}/{}",
+                    publisher.published_port, publisher.target_port, publisher.protocol
+                )
+            })
+            .collect();
+        // Ports bound on IPv4 and IPv6 are listed once for each
+        published.dedup();
+
+        Self {
+            service: container.service.clone(),
+            state: container.state.clone(),
+            health: container.health.clone(),
+            published,
+            port,
+            readiness: readiness(container, port.map(|(_, open)| open)),
+        }
+    }
+}
+
+/// Redraws the status table in place when stdout is a terminal, otherwise only prints its last
+/// state
+struct Table {
+    live: bool,
+    printed: u16,
+}
+
+impl Table {
+    /// Draw `rows`, replacing the previous table when live, only the `last` one otherwise
+    ///
+    /// The table is cosmetic, so a closed stdout doesn't fail the action
+    fn render(&mut self, rows: &[Row], last: bool) {
+        let _ = self.draw(rows, last);
+    }
+
+    fn draw(&mut self, rows: &[Row], last: bool) -> io::Result<()> {
+        if !self.live && !last {
+            return Ok(());
+        }
+
+        let mut stdout = io::stdout();
+
+        if self.printed > 0 {
+            stdout
+                .queue(MoveUp(self.printed))?
+                .queue(Clear(ClearType::FromCursorDown))?;
+        }
+
+        let width = rows
+            .iter()
+            .map(|row| row.service.len())
+            .chain([7])
+            .max()
+            .unwrap_or_default();
+
+        let header = format!(
+            "{:<width$}  {:<10}  {:<10}  PORTS",
+            "SERVICE", "STATE", "HEALTH"
+        );
+        writeln!(stdout, " {}", header.bold())?;
+
+        for row in rows {
+            let mut ports = row.published.clone();
+            match row.port {
+                Some((port, true)) => ports.push(format!("{} open", port)),
+                Some((port, false)) => ports.push(format!("{} closed", port)),
+                None => {}
+            }
+            let port = match ports.is_empty() {
+                true => String::from("-"),
+                false => ports.join(", "),
+            };
+            let line = format!(
+                "{:<width$}  {:<10}  {:<10}  {}",
+                row.service,
+                row.state,
+                if row.health.is_empty() {
+                    "-"
+                } else {
+                    &row.health
+                },
+                port,
+            );
+
+            let line = match row.readiness {
+                Readiness::Ready => line.green(),
+                Readiness::Waiting => line.dark_yellow(),
+                Readiness::Failed => line.dark_red(),
+            };
+
+            writeln!(stdout, " {}", line)?;
+        }
+
+        self.printed = rows.len() as u16 + 1;
+
+        stdout.flush()
+    }
+}
+
+/// Print the table of the containers of `services`, every service when empty
+pub fn print(
+    runner: &mut dyn CommandRunner,
+    compose: &[&str],
+    services: &[&str],
+) -> Result<(), LateremError> {
+    let ps = [compose, &["ps", "--all", "--format", "json"], services].concat();
+    let output = runner.capture("docker", &ps)?;
+    let rows: Vec<Row> = parse(&output, &ps)?
+        .iter()
+        .map(|container| Row::new(container, &BTreeMap::new()))
+        .collect();
+
+    if rows.is_empty() {
+        println!(" {}", "No container was created for the project".dim());
+
+        return Ok(());
+    }
+
+    let mut table = Table {
+        live: false,
+        printed: 0,
+    };
+
+    table.render(&rows, true);
+
+    Ok(())
+}
+
+/// Poll the containers of `services`, every service when empty, until each one is healthy and
+/// its port in `ports` accepts connections, failing with their last log lines once `timeout`
+/// runs out or a container stops
+pub fn wait(
+    runner: &mut dyn CommandRunner,
+    compose: &[&str],
+    services: &[&str],
+    ports: &BTreeMap<String, u16>,
+    timeout: Duration,
+) -> Result<(), LateremError> {
+    println!(
+        "\t\n{}{}{}{}\t\n",
+        "Waiting for the services to be healthy".dim(),
+        ".".rapid_blink(),
+        ".".rapid_blink(),
+        ".".dim(),
+    );
+
+    let deadline = Instant::now() + timeout;
+    let ps = [compose, &["ps", "--all", "--format", "json"], services].concat();
+    let mut table = Table {
+        live: io::stdout().is_terminal(),
+        printed: 0,
+    };
+
+    loop {
+        let output = runner.capture("docker", &ps)?;
+        let rows: Vec<Row> = parse(&output, &ps)?
+            .iter()
+            .map(|container| Row::new(container, ports))
+            .collect();
+
+        let pending: Vec<String> = rows
+            .iter()
+            .filter(|row| row.readiness != Readiness::Ready)
+            .map(|row| row.service.clone())
+            .collect();
+        let stopped: Vec<String> = rows
+            .iter()
+            .filter(|row| row.readiness == Readiness::Failed)
+            .map(|row| row.service.clone())
+            .collect();
+        let last = pending.is_empty() || !stopped.is_empty() || Instant::now() >= deadline;
+
+        table.render(&rows, last);
+
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        if !stopped.is_empty() {
+            print_logs(runner, compose, &stopped);
+
+            return Err(LateremError::Stopped { services: stopped });
+        }
+
+        if last {
+            print_logs(runner, compose, &pending);
+
+            return Err(LateremError::Unhealthy {
+                services: pending,
+                timeout: timeout.as_secs(),
+            });
+        }
+
+        thread::sleep(POLL);
+    }
+}
+
+/// Containers of the output of `ps`, a JSON array for compose up to 2.20 and a JSON object per
+/// line since then
+pub fn parse(output: &str, ps: &[&str]) -> Result<Vec<Container>, LateremError> {
+    let output = output.trim();
+    let containers = if output.starts_with('[') {
+        serde_json::from_str(output)
+    } else {
+        output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect()
+    };
+
+    containers.map_err(|error| LateremError::UnexpectedOutput {
+        command: [&["docker"], ps].concat().join(" "),
+        reason: error.to_string(),
+    })
+}
+
+/// Containers that exited successfully are one-off tasks, e.g. migrations, and count as ready
+fn readiness(container: &Container, port: Option<bool>) -> Readiness {
+    match container.state.as_str() {
+        "exited" if container.exit_code == 0 => Readiness::Ready,
+        "exited" | "dead" => Readiness::Failed,
+        "running" => match (container.health.as_str(), port) {
+            ("" | "healthy", None | Some(true)) => Readiness::Ready,
+            _ => Readiness::Waiting,
+        },
+        _ => Readiness::Waiting,
+    }
+}
+
+fn accepts(port: u16) -> bool {
+    TcpStream::connect_timeout(&SocketAddr::from((Ipv4Addr::LOCALHOST, port)), CONNECT).is_ok()
+}
+
+fn print_logs(runner: &mut dyn CommandRunner, compose: &[&str], services: &[String]) {
+    let services: Vec<&str> = services.iter().map(String::as_str).collect();
+    let logs = runner.capture(
+        "docker",
+        &[
+            compose,
+            &["logs", "--no-color", "--tail", LOG_LINES],
+            &services,
+        ]
+        .concat(),
+    );
+
+    println!(
+        "\t\n{}\t\n",
+        " LAST LOG LINES ".on_dark_red().white().bold()
+    );
+
+    match logs {
+        Ok(logs) => {
+            for line in logs.lines() {
+                println!(" {}", line.dim());
+            }
+        }
+        Err(error) => println!(" {} {}", "Warning:".dark_yellow().bold(), error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::utils::runner::RecordingRunner;
+
+    const PS: &str = "docker compose ps --all --format json";
+
+    fn container(state: &str, health: &str, exit_code: i64) -> Container {
+        Container {
+            service: String::from("api"),
+            state: state.to_string(),
+            health: health.to_string(),
+            exit_code,
+            publishers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn both_output_formats_are_parsed() {
+        let lines = "{\"Service\":\"api\",\"State\":\"running\",\"Health\":\"healthy\"}\n\
+                     {\"Service\":\"worker\",\"State\":\"running\",\"Health\":\"\"}";
+        let array = "[{\"Service\":\"api\",\"State\":\"running\"}]";
+
+        assert_eq!(parse(lines, &[]).unwrap().len(), 2);
+        assert_eq!(parse(array, &[]).unwrap()[0].service, "api");
+        assert!(parse("", &[]).unwrap().is_empty());
+        assert!(parse("not json", &[]).is_err());
+    }
+
+    #[test]
+    fn published_ports_are_listed_once() {
+        let output = r#"{"Service":"database","State":"running","Publishers":[
+            {"URL":"0.0.0.0","TargetPort":5432,"PublishedPort":5432,"Protocol":"tcp"},
+            {"URL":"::","TargetPort":5432,"PublishedPort":5432,"Protocol":"tcp"},
+            {"URL":"","TargetPort":8080,"PublishedPort":0,"Protocol":"tcp"}]}"#
+            .replace('\n', "");
+
+        let container = &parse(&output, &[]).unwrap()[0];
+        let row = Row::new(container, &BTreeMap::new());
+
+        assert_eq!(row.published, ["5432->5432/tcp"]);
+    }
+
+    #[test]
+    fn readiness_follows_the_state_health_and_port() {
+        let cases = [
+            (container("running", "", 0), None, Readiness::Ready),
+            (container("running", "healthy", 0), None, Readiness::Ready),
+            (
+                container("running", "starting", 0),
+                None,
+                Readiness::Waiting,
+            ),
+            (container("running", "", 0), Some(false), Readiness::Waiting),
+            (container("running", "", 0), Some(true), Readiness::Ready),
+            (container("restarting", "", 0), None, Readiness::Waiting),
+            (container("exited", "", 0), None, Readiness::Ready),
+            (container("exited", "", 1), None, Readiness::Failed),
+        ];
+
+        for (container, port, expected) in cases {
+            assert_eq!(
+                readiness(&container, port),
+                expected,
+                "{} {}",
+                container.state,
+                container.health
+            );
+        }
+    }
+
+    #[test]
+    fn open_port_makes_the_service_ready() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let ports = BTreeMap::from([(String::from("api"), port)]);
+
+        let row = Row::new(&container("running", "", 0), &ports);
+
+        assert_eq!(row.readiness, Readiness::Ready);
+    }
+
+    #[test]
+    fn healthy_services_end_the_wait() {
+        let mut runner = RecordingRunner::new().output(
+            PS,
+            "{\"Service\":\"api\",\"State\":\"running\",\"Health\":\"healthy\"}",
+        );
+
+        let result = wait(
+            &mut runner,
+            &["compose"],
+            &[],
+            &BTreeMap::new(),
+            Duration::from_secs(5),
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(runner.calls, [PS]);
+    }
+
+    #[test]
+    fn stopped_service_fails_with_its_logs() {
+        let mut runner = RecordingRunner::new().output(
+            "docker compose ps --all --format json api",
+            "{\"Service\":\"api\",\"State\":\"exited\",\"ExitCode\":1}",
+        );
+
+        let error = wait(
+            &mut runner,
+            &["compose"],
+            &["api"],
+            &BTreeMap::new(),
+            Duration::from_secs(5),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.exit_code(), 5);
+        assert_eq!(
+            error.to_string(),
+            "`api` exited with an error before becoming healthy"
+        );
+        assert_eq!(
+            runner.calls[1],
+            "docker compose logs --no-color --tail 20 api"
+        );
+    }
+
+    #[test]
+    fn unhealthy_service_times_out() {
+        let mut runner = RecordingRunner::new().output(
+            PS,
+            "{\"Service\":\"api\",\"State\":\"running\",\"Health\":\"starting\"}",
+        );
+
+        let result = wait(
+            &mut runner,
+            &["compose"],
+            &[],
+            &BTreeMap::new(),
+            Duration::ZERO,
+        );
+
+        assert!(matches!(result, Err(LateremError::Unhealthy { .. })));
+    }
+}
@@ -0,0 +1,580 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Display,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crossterm::style::Stylize;
+use serde_json::{Map, Value};
+
+use super::{
+    entities::{DefaultConfig, LateremError},
+    migrations::{self, CURRENT_VERSION, VERSION_KEY},
+    settings,
+};
+
+/// Per-repository config file, looked up from the current directory to the git root
+pub const REPOSITORY_FILE: &str = ".laterem.json";
+
+/// Prefix of the environment variables overriding config fields
+pub const ENVIRONMENT_PREFIX: &str = "LATEREM_";
+
+/// Environment variable selecting the profile when `--profile` is missing
+pub const PROFILE_ENV: &str = "LATEREM_PROFILE";
+
+/// Key holding the named profiles of a config file
+pub const PROFILES_KEY: &str = "profiles";
+
+/// Key holding the profile used when none is selected
+pub const DEFAULT_PROFILE_KEY: &str = "default_profile";
+
+/// Where an effective config value came from
+#[derive(Clone)]
+pub enum Origin {
+    Default,
+    Global(PathBuf),
+    Repository(PathBuf),
+    Environment(String),
+    CommandLine,
+    Profile(String, Box<Origin>),
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::Global(path) => write!(f, "global {}", path.display()),
+            Origin::Repository(path) => write!(f, "repository {}", path.display()),
+            Origin::Environment(name) => write!(f, "environment {}", name),
+            Origin::CommandLine => write!(f, "command line"),
+            Origin::Profile(name, origin) => write!(f, "profile {} in {}", name, origin),
+        }
+    }
+}
+
+/// Values set by a single config source, alongside the named profiles it declares
+pub struct Layer {
+    pub origin: Origin,
+    pub values: Map<String, Value>,
+    pub profiles: BTreeMap<String, Map<String, Value>>,
+    pub default_profile: Option<String>,
+    /// schema version the file was written with, `values` being migrated to the current one
+    pub version: u64,
+}
+
+impl Layer {
+    pub fn new(origin: Origin, values: Map<String, Value>) -> Self {
+        Self {
+            origin,
+            values,
+            profiles: BTreeMap::new(),
+            default_profile: None,
+            version: CURRENT_VERSION,
+        }
+    }
+
+    /// Read the JSON object at `path`, `None` when the file doesn't exist
+    ///
+    /// An outdated file is always migrated in memory, it's only rewritten with `upgrade`, see
+    /// [`Layer::warn_outdated`] otherwise
+    pub fn read(path: &Path, origin: Origin, upgrade: bool) -> Result<Option<Self>, LateremError> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(LateremError::ConfigIo {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            }
+        };
+
+        let invalid = |reason: &str| LateremError::ConfigParse {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        };
+
+        let mut values = match serde_json::from_slice(&content) {
+            Ok(Value::Object(values)) => values,
+            Ok(_) => return Err(invalid("the file must contain a JSON object")),
+            Err(error) => return Err(invalid(&error.to_string())),
+        };
+
+        let version = migrations::migrate(&mut values).map_err(|error| match error {
+            LateremError::InvalidConfig(reason) => invalid(&reason),
+            error => error,
+        })?;
+        values.remove(VERSION_KEY);
+
+        let profiles = match values.remove(PROFILES_KEY) {
+            None => BTreeMap::new(),
+            Some(Value::Object(profiles)) => profiles
+                .into_iter()
+                .map(|(name, profile)| match profile {
+                    Value::Object(profile) => Ok((name, profile)),
+                    _ => Err(invalid(&format!(
+                        "profile `{}` must be a JSON object",
+                        name
+                    ))),
+                })
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err(invalid("`profiles` must be a JSON object")),
+        };
+
+        let default_profile = match values.remove(DEFAULT_PROFILE_KEY) {
+            None | Some(Value::Null) => None,
+            Some(Value::String(name)) => Some(name),
+            Some(_) => return Err(invalid("`default_profile` must be a string")),
+        };
+
+        let layer = Self {
+            origin,
+            values,
+            profiles,
+            default_profile,
+            version,
+        };
+
+        if version < CURRENT_VERSION && upgrade {
+            Layer::upgrade(path, &content, &layer.document())?;
+
+            println!(
+                " - {} {} {}\t\n",
+                format!("Migrated config from version {} to", version)
+                    .dark_magenta()
+                    .bold(),
+                CURRENT_VERSION,
+                format!("({})", path.display()).dim(),
+            );
+        }
+
+        Ok(Some(layer))
+    }
+
+    /// Tell that the file is read migrated in memory, when it was written by an older laterem
+    pub fn warn_outdated(&self) {
+        if self.version >= CURRENT_VERSION {
+            return;
+        }
+
+        println!(
+            " - {} {} {}\t\n",
+            format!(
+                "Outdated config version {}, a run upgrades it to",
+                self.version
+            )
+            .dark_yellow()
+            .bold(),
+            CURRENT_VERSION,
+            format!("({})", self.origin).dim(),
+        );
+    }
+
+    /// Every value of the file, including its profiles
+    pub fn document(&self) -> Map<String, Value> {
+        let mut document = self.values.clone();
+
+        if !self.profiles.is_empty() {
+            let profiles = self
+                .profiles
+                .iter()
+                .map(|(name, profile)| (name.clone(), Value::Object(profile.clone())));
+
+            document.insert(PROFILES_KEY.to_string(), Value::Object(profiles.collect()));
+        }
+
+        if let Some(name) = &self.default_profile {
+            document.insert(DEFAULT_PROFILE_KEY.to_string(), Value::String(name.clone()));
+        }
+
+        document
+    }
+
+    /// The values of the profile `name`, layered right above the values shared by every profile
+    pub fn profile(&self, name: &str) -> Option<Self> {
+        self.profiles.get(name).map(|values| {
+            Self::new(
+                Origin::Profile(name.to_string(), Box::new(self.origin.clone())),
+                values.clone(),
+            )
+        })
+    }
+
+    /// A layer for every non-empty `LATEREM_<FIELD>` variable, e.g: `LATEREM_STASH_FILES=false`
+    pub fn environment() -> Vec<Self> {
+        // `env::vars` panics on a variable that isn't valid unicode, those are never ours
+        Layer::variables(env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }))
+    }
+
+    /// The layers of [`Layer::environment`] out of the `(name, value)` pairs of `variables`
+    fn variables(variables: impl IntoIterator<Item = (String, String)>) -> Vec<Self> {
+        let variables: BTreeMap<String, String> = variables.into_iter().collect();
+
+        DefaultConfig::keys()
+            .into_iter()
+            .filter_map(|key| {
+                let name = format!("{}{}", ENVIRONMENT_PREFIX, key.to_uppercase());
+                let raw = variables.get(&name).filter(|raw| !raw.is_empty())?;
+
+                Some(Self::new(
+                    Origin::Environment(name),
+                    Map::from_iter([(key.clone(), parse_value(&key, raw))]),
+                ))
+            })
+            .collect()
+    }
+
+    /// Keep the original `content` next to `path` as a `.bak` file, then rewrite it with `values`
+    fn upgrade(
+        path: &Path,
+        content: &[u8],
+        values: &Map<String, Value>,
+    ) -> Result<(), LateremError> {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+
+        fs::write(&backup, content).map_err(|source| LateremError::ConfigIo {
+            path: PathBuf::from(backup),
+            source,
+        })?;
+
+        write(path, values)
+    }
+
+    /// Print a warning for every key that doesn't match a config field
+    pub fn warn_unknown(&self) {
+        let keys = DefaultConfig::keys();
+
+        for key in self.values.keys().filter(|key| !keys.contains(key)) {
+            println!(
+                " {} {} {}",
+                "Warning:".dark_yellow().bold(),
+                settings::describe_unknown(key, &keys),
+                format!("({})", self.origin).dim(),
+            );
+        }
+    }
+}
+
+/// Parse the raw value of `key` as JSON, anything that isn't valid JSON (e.g. a bare branch name)
+/// or that only fits the field as a string (e.g. `2024` for `branch`) is a string
+pub fn parse_value(key: &str, raw: &str) -> Value {
+    let text = Value::String(raw.to_string());
+    let fits = |value: &Value| {
+        DefaultConfig::check(&Map::from_iter([(key.to_string(), value.clone())])).is_ok()
+    };
+
+    match serde_json::from_str(raw) {
+        Ok(value) if fits(&value) || !fits(&text) => value,
+        _ => text,
+    }
+}
+
+/// Write `values` as pretty printed JSON stamped with the current schema version, creating every
+/// missing parent directory
+pub fn write(path: &Path, values: &Map<String, Value>) -> Result<(), LateremError> {
+    let unable = |source| LateremError::ConfigIo {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(unable)?;
+    }
+
+    let mut values = values.clone();
+    values.insert(VERSION_KEY.to_string(), CURRENT_VERSION.into());
+
+    let content =
+        serde_json::to_string_pretty(&values).map_err(|error| LateremError::ConfigParse {
+            path: path.to_path_buf(),
+            reason: error.to_string(),
+        })?;
+
+    fs::write(path, content + "\n").map_err(unable)
+}
+
+/// Root of the git repository containing `start`
+pub fn repository_root(start: &Path) -> Option<&Path> {
+    start
+        .ancestors()
+        .find(|directory| directory.join(".git").exists())
+}
+
+/// Find the closest `.laterem.json` between `start` and the root of its git repository
+pub fn repository_file(start: &Path) -> Option<PathBuf> {
+    let mut candidate = None;
+
+    for directory in start.ancestors() {
+        let file = directory.join(REPOSITORY_FILE);
+
+        if candidate.is_none() && file.is_file() {
+            candidate = Some(file);
+        }
+
+        if directory.join(".git").exists() {
+            return candidate;
+        }
+    }
+
+    None
+}
+
+/// Insert the selected profile of every file right after it, the selection being `profile`,
+/// `$LATEREM_PROFILE` or the `default_profile` of the last file declaring one
+pub fn select_profile(
+    files: Vec<Layer>,
+    profile: Option<String>,
+) -> Result<Vec<Layer>, LateremError> {
+    let name = profile
+        .or_else(|| env::var(PROFILE_ENV).ok())
+        .filter(|name| !name.is_empty())
+        .or_else(|| {
+            files
+                .iter()
+                .rev()
+                .find_map(|file| file.default_profile.clone())
+        });
+
+    let Some(name) = name else {
+        return Ok(files);
+    };
+
+    let mut layers = Vec::new();
+    let mut found = false;
+
+    for file in files {
+        let selected = file.profile(&name);
+        found |= selected.is_some();
+
+        layers.push(file);
+        layers.extend(selected);
+    }
+
+    if !found {
+        let available: Vec<String> = layers
+            .iter()
+            .flat_map(|layer| layer.profiles.keys().cloned())
+            .collect();
+
+        if available.is_empty() {
+            return Err(LateremError::InvalidConfig(format!(
+                "unknown profile `{}`, no config file declares profiles",
+                name
+            )));
+        }
+
+        return Err(LateremError::InvalidConfig(format!(
+            "unknown profile `{}`, available profiles: {}",
+            name,
+            available.join(", ")
+        )));
+    }
+
+    Ok(layers)
+}
+
+/// Merge `layers` in order, later layers taking precedence, and detect every field left unset
+pub fn resolve(
+    layers: &[Layer],
+) -> Result<(DefaultConfig, BTreeMap<String, Origin>), LateremError> {
+    let mut merged = Map::new();
+    let mut origins = BTreeMap::new();
+
+    for layer in layers {
+        layer.warn_unknown();
+
+        DefaultConfig::check(&layer.values).map_err(|error| {
+            LateremError::InvalidConfig(format!("{} ({})", error, layer.origin))
+        })?;
+
+        for (key, value) in &layer.values {
+            merged.insert(key.clone(), value.clone());
+            origins.insert(key.clone(), layer.origin.clone());
+        }
+    }
+
+    let mut defaults = DefaultConfig::check(&merged)
+        .map_err(|error| LateremError::InvalidConfig(error.to_string()))?;
+    defaults.detect_missing()?;
+
+    let values = defaults.values();
+
+    origins.retain(|key, _| values.contains_key(key));
+    for key in values.keys() {
+        origins.entry(key.clone()).or_insert(Origin::Default);
+    }
+
+    Ok((defaults, origins))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn layer(origin: Origin, value: Value) -> Layer {
+        match value {
+            Value::Object(values) => Layer::new(origin, values),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn later_layers_take_precedence() {
+        let global = PathBuf::from("global.json");
+        let repository = PathBuf::from(".laterem.json");
+
+        let (defaults, origins) = resolve(&[
+            layer(
+                Origin::Global(global.clone()),
+                json!({ "branch": "main", "stash_files": false }),
+            ),
+            layer(
+                Origin::Repository(repository.clone()),
+                json!({ "branch": "develop" }),
+            ),
+            layer(
+                Origin::Environment(String::from("LATEREM_DETACH_CONTAINER")),
+                json!({ "detach_container": false }),
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(defaults.branch, "develop");
+        assert!(!defaults.stash_files);
+        assert!(!defaults.detach_container);
+
+        assert_eq!(origins["branch"].to_string(), "repository .laterem.json");
+        assert_eq!(origins["stash_files"].to_string(), "global global.json");
+        assert_eq!(
+            origins["detach_container"].to_string(),
+            "environment LATEREM_DETACH_CONTAINER"
+        );
+        assert_eq!(origins["compose_files"].to_string(), "default");
+    }
+
+    #[test]
+    fn invalid_values_name_their_layer() {
+        let error = resolve(&[layer(
+            Origin::Environment(String::from("LATEREM_STASH_FILES")),
+            json!({ "branch": "main", "stash_files": "maybe" }),
+        )])
+        .err()
+        .unwrap();
+
+        assert!(error.to_string().contains("LATEREM_STASH_FILES"));
+    }
+
+    fn profiles(origin: Origin, value: Value, default_profile: Option<&str>) -> Layer {
+        let mut file = layer(origin, json!({}));
+
+        if let Value::Object(profiles) = value {
+            for (name, profile) in profiles {
+                file.profiles
+                    .insert(name, layer(Origin::Default, profile).values);
+            }
+        }
+
+        file.default_profile = default_profile.map(String::from);
+        file
+    }
+
+    #[test]
+    fn selected_profile_sits_above_its_file() {
+        let global = profiles(
+            Origin::Global(PathBuf::from("global.json")),
+            json!({ "work": { "branch": "develop", "remote": "upstream" } }),
+            None,
+        );
+        let repository = layer(
+            Origin::Repository(PathBuf::from(".laterem.json")),
+            json!({ "branch": "main" }),
+        );
+
+        let layers = select_profile(vec![global, repository], Some(String::from("work"))).unwrap();
+        let (defaults, origins) = resolve(&layers).unwrap();
+
+        assert_eq!(defaults.branch, "main");
+        assert_eq!(defaults.remote, "upstream");
+        assert_eq!(
+            origins["remote"].to_string(),
+            "profile work in global global.json"
+        );
+    }
+
+    #[test]
+    fn default_profile_of_the_last_file_wins() {
+        let global = profiles(
+            Origin::Global(PathBuf::from("global.json")),
+            json!({ "work": { "branch": "develop" }, "oss": { "branch": "trunk" } }),
+            Some("work"),
+        );
+        let repository = profiles(
+            Origin::Repository(PathBuf::from(".laterem.json")),
+            json!({}),
+            Some("oss"),
+        );
+
+        let layers = select_profile(vec![global, repository], None).unwrap();
+        let (defaults, _) = resolve(&layers).unwrap();
+
+        assert_eq!(defaults.branch, "trunk");
+    }
+
+    #[test]
+    fn unknown_profile_lists_the_available_ones() {
+        let global = profiles(
+            Origin::Global(PathBuf::from("global.json")),
+            json!({ "work": {}, "oss": {} }),
+            None,
+        );
+
+        let error = select_profile(vec![global], Some(String::from("wrok")))
+            .err()
+            .unwrap();
+
+        assert!(error.to_string().contains("oss, work"));
+    }
+
+    #[test]
+    fn raw_values_fall_back_to_strings() {
+        assert_eq!(parse_value("stash_files", "false"), json!(false));
+        assert_eq!(parse_value("project_name", "null"), Value::Null);
+        assert_eq!(parse_value("branch", "develop"), json!("develop"));
+        assert_eq!(parse_value("wait_timeout", "30"), json!(30));
+    }
+
+    #[test]
+    fn raw_values_follow_the_type_of_the_field() {
+        assert_eq!(parse_value("branch", "2024"), json!("2024"));
+        assert_eq!(parse_value("project_name", "2024"), json!("2024"));
+        assert_eq!(parse_value("compose_files", "true"), json!("true"));
+        assert_eq!(
+            parse_value("compose_files", r#"["a.yml", "b.yml"]"#),
+            json!(["a.yml", "b.yml"])
+        );
+        assert_eq!(parse_value("wait_timeout", "soon"), json!("soon"));
+    }
+
+    #[test]
+    fn numeric_branch_from_the_environment_is_valid() {
+        let layers = Layer::variables([
+            (String::from("LATEREM_BRANCH"), String::from("2024")),
+            (String::from("LATEREM_REMOTE"), String::new()),
+            (String::from("BRANCH"), String::from("develop")),
+        ]);
+
+        let (defaults, origins) = resolve(&layers).unwrap();
+
+        assert_eq!(layers.len(), 1);
+        assert_eq!(defaults.branch, "2024");
+        assert_eq!(origins["branch"].to_string(), "environment LATEREM_BRANCH");
+    }
+}
@@ -0,0 +1,191 @@
+use serde_json::{Map, Value};
+
+use super::{entities::LateremError, layers::PROFILES_KEY};
+
+/// Key holding the schema version of a config file, files without it are version 0
+pub const VERSION_KEY: &str = "version";
+
+/// Schema version written by this build
+pub const CURRENT_VERSION: u64 = 2;
+
+type Migration = fn(&mut Map<String, Value>);
+
+/// Each entry upgrades a file from the version matching its index to the next one
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2];
+
+/// Upgrade `values` to `CURRENT_VERSION`, returning the version it was written with
+pub fn migrate(values: &mut Map<String, Value>) -> Result<u64, LateremError> {
+    let version = match values.get(VERSION_KEY) {
+        None => 0,
+        Some(version) => version.as_u64().ok_or_else(|| {
+            LateremError::InvalidConfig(format!("invalid `{}`: {}", VERSION_KEY, version))
+        })?,
+    };
+
+    if version > CURRENT_VERSION {
+        return Err(LateremError::InvalidConfig(format!(
+            "version {} is newer than the supported version {}, update laterem",
+            version, CURRENT_VERSION
+        )));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(values);
+    }
+
+    values.insert(VERSION_KEY.to_string(), CURRENT_VERSION.into());
+
+    Ok(version)
+}
+
+/// Files written before versioning already match the first schema
+fn v0_to_v1(_values: &mut Map<String, Value>) {}
+
+/// `compose_file` became the ordered `compose_files` list, in the file and in every profile
+fn v1_to_v2(values: &mut Map<String, Value>) {
+    fn rename(values: &mut Map<String, Value>) {
+        match values.remove("compose_file") {
+            Some(Value::Null) | None => {}
+            Some(file) => {
+                values.insert(String::from("compose_files"), Value::Array(vec![file]));
+            }
+        }
+    }
+
+    rename(values);
+
+    if let Some(Value::Object(profiles)) = values.get_mut(PROFILES_KEY) {
+        for profile in profiles.values_mut() {
+            if let Value::Object(profile) = profile {
+                rename(profile);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::utils::layers::{Layer, Origin};
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(values) => values,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn v0_to_v1_keeps_every_field() {
+        let mut values = object(json!({ "branch": "main", "stash_files": false }));
+
+        assert_eq!(migrate(&mut values).unwrap(), 0);
+        assert_eq!(
+            Value::Object(values),
+            json!({ "branch": "main", "stash_files": false, "version": CURRENT_VERSION })
+        );
+    }
+
+    #[test]
+    fn v1_to_v2_turns_the_compose_file_into_a_list() {
+        let mut values = object(json!({
+            "version": 1,
+            "compose_file": "docker-compose.dev.yml",
+            "profiles": { "oss": { "compose_file": "compose.yml" }, "work": {} },
+        }));
+
+        assert_eq!(migrate(&mut values).unwrap(), 1);
+        assert_eq!(
+            Value::Object(values),
+            json!({
+                "version": 2,
+                "compose_files": ["docker-compose.dev.yml"],
+                "profiles": { "oss": { "compose_files": ["compose.yml"] }, "work": {} },
+            })
+        );
+    }
+
+    #[test]
+    fn current_version_is_untouched() {
+        let mut values = object(json!({ "branch": "main", "version": CURRENT_VERSION }));
+        let expected = values.clone();
+
+        assert_eq!(migrate(&mut values).unwrap(), CURRENT_VERSION);
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut values = object(json!({ "version": CURRENT_VERSION + 1 }));
+
+        assert!(migrate(&mut values).is_err());
+    }
+
+    #[test]
+    fn invalid_version_is_rejected() {
+        let mut values = object(json!({ "version": "one" }));
+
+        assert!(migrate(&mut values).is_err());
+    }
+
+    #[test]
+    fn outdated_file_is_upgraded_in_place_with_a_backup() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.json");
+        let original = r#"{ "branch": "develop", "stash_files": false }"#;
+        fs::write(&path, original).unwrap();
+
+        let layer = Layer::read(&path, Origin::Global(path.clone()), true)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            Value::Object(layer.values),
+            json!({ "branch": "develop", "stash_files": false })
+        );
+
+        let upgraded: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(upgraded[VERSION_KEY], json!(CURRENT_VERSION));
+
+        let backup = directory.path().join("config.json.bak");
+        assert_eq!(fs::read_to_string(backup).unwrap(), original);
+    }
+
+    #[test]
+    fn current_file_is_left_alone() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.json");
+        fs::write(
+            &path,
+            format!(r#"{{ "branch": "main", "version": {} }}"#, CURRENT_VERSION),
+        )
+        .unwrap();
+
+        Layer::read(&path, Origin::Global(path.clone()), true).unwrap();
+
+        assert!(!directory.path().join("config.json.bak").exists());
+    }
+
+    #[test]
+    fn outdated_file_is_migrated_in_memory_without_upgrade() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.json");
+        let original = r#"{ "compose_file": "compose.yml" }"#;
+        fs::write(&path, original).unwrap();
+
+        let layer = Layer::read(&path, Origin::Global(path.clone()), false)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            Value::Object(layer.values),
+            json!({ "compose_files": ["compose.yml"] })
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert!(!directory.path().join("config.json.bak").exists());
+    }
+}
@@ -1,2 +1,11 @@
 pub mod parser;
 pub mod entities;
+pub mod paths;
+pub mod wizard;
+pub mod layers;
+pub mod settings;
+pub mod migrations;
+pub mod runner;
+pub mod pipeline;
+pub mod git;
+pub mod health;
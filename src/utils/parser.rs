@@ -1,72 +1,566 @@
-use std::process::Command;
+use std::{collections::BTreeMap, process::Command};
 
-use clap::Parser;
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use crossterm::style::Stylize;
+use serde_json::{Map, Value};
 
-use super::entities::{Action, Config, Target};
+use super::{
+    entities::{Action, Config, Target},
+    paths,
+};
 
 #[derive(Parser)]
-#[command(version)]
+#[command(
+    version,
+    subcommand_value_name = "TARGET",
+    subcommand_help_heading = "Targets"
+)]
 pub struct Cli {
-    /// TODO: Configure the behaviour of the cli
-    #[arg(short, long)]
-    config: Option<String>,
+    #[command(subcommand)]
+    target: TargetCommand,
 
-    /// The target of the action, e.g: `docker` or `repository`
-    target: String,
-
-    /// Select the target action, e.g: `reset`, `commit`, `push`, `pull`, `down`, and `up`
-    action: Option<String>,
+    /// Path to the config file, defaults to `$LATEREM_CONFIG` or `$XDG_CONFIG_HOME/laterem/config.json`
+    #[arg(short, long, global = true)]
+    config: Option<String>,
 
     /// Arguments for the base cli command
-    #[arg(short, long = "args")]
+    #[arg(short, long = "args", global = true)]
     arguments: Vec<String>,
+
+    /// Create the missing config file without prompting, every field keeping its default
+    #[arg(long, global = true)]
+    non_interactive: bool,
+
+    /// Print every effective config value and the layer it came from
+    #[arg(long, global = true)]
+    show_origin: bool,
+
+    /// Make `config` actions use the repository `.laterem.json` instead of the global file
+    #[arg(long, global = true)]
+    local: bool,
+
+    /// Select a named profile from the config files, defaults to `$LATEREM_PROFILE`
+    #[arg(short, long, global = true)]
+    profile: Option<String>,
+
+    /// Print every git and docker command the action would run without running it, not
+    /// available for `config`
+    #[arg(long, global = true)]
+    dry_run: bool,
+}
+
+// Each target only accepts the actions it supports, so invalid combinations fail to parse
+#[derive(Subcommand)]
+enum TargetCommand {
+    /// Restart, start or stop the compose services
+    #[command(visible_alias = "d")]
+    Docker {
+        #[arg(value_enum, default_value_t = DockerAction::Reset)]
+        action: DockerAction,
+
+        /// Services to act on, defaults to every service of the compose project, `exec` takes
+        /// the service followed by the command, e.g: `exec api -- ls -la`
+        services: Vec<String>,
+
+        #[command(flatten)]
+        logs: LogOptions,
+
+        #[command(flatten)]
+        nuke: NukeOptions,
+
+        #[command(flatten)]
+        project: ComposeProject,
+    },
+    /// Update the default branch, or commit, push and pull the current one
+    #[command(visible_alias = "r")]
+    Repository {
+        #[arg(value_enum, default_value_t = RepositoryAction::Reset)]
+        action: RepositoryAction,
+
+        /// Turn `reset` into `sync`, rebasing whatever `sync_strategy` says
+        #[arg(long)]
+        rebase: bool,
+    },
+    /// Like `repository`, resetting the current branch instead of the default one
+    #[command(visible_alias = "rc")]
+    Current {
+        #[arg(value_enum, default_value_t = RepositoryAction::Reset)]
+        action: RepositoryAction,
+
+        /// Turn `reset` into `sync`, rebasing whatever `sync_strategy` says
+        #[arg(long)]
+        rebase: bool,
+    },
+    /// Read, write and check the config files
+    #[command(visible_alias = "cfg")]
+    Config {
+        #[arg(value_enum, default_value_t = ConfigAction::List)]
+        action: ConfigAction,
+
+        /// Values for the action, e.g: the key and value of `config set`
+        values: Vec<String>,
+    },
+}
+
+/// Flags of the `logs` docker action
+#[derive(Args, Default)]
+struct LogOptions {
+    /// Keep printing the new lines of `logs` until interrupted
+    #[arg(long)]
+    follow: bool,
+
+    /// Only print the lines of `logs` since a timestamp or a duration, e.g: `10m`
+    #[arg(long, value_name = "TIME")]
+    since: Option<String>,
+}
+
+/// Flags of the `nuke` docker action
+#[derive(Args, Default)]
+struct NukeOptions {
+    /// Turn `reset` into `nuke`, removing the volumes and orphans too
+    #[arg(long)]
+    hard: bool,
+
+    /// Make `nuke` rebuild the images without the build cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Destroy the volumes of `nuke` without asking first
+    #[arg(short, long)]
+    yes: bool,
+}
+
+/// Flags overriding the compose settings of the config files
+#[derive(Args)]
+struct ComposeProject {
+    /// Compose file, repeat it to merge several files in order
+    #[arg(short = 'f', long = "compose-file", value_name = "FILE")]
+    compose_files: Vec<String>,
+
+    /// Compose project name
+    #[arg(long)]
+    project_name: Option<String>,
+
+    /// Environment file for compose, repeat it to layer several files
+    #[arg(long = "env-file", value_name = "FILE")]
+    env_files: Vec<String>,
+
+    /// Project directory of compose, the relative paths inside the compose files resolve from it
+    #[arg(long, value_name = "DIRECTORY")]
+    working_directory: Option<String>,
+}
+
+impl ComposeProject {
+    /// The config values set by the flags, keyed like the config file
+    fn overrides(self) -> Map<String, Value> {
+        let mut overrides = Map::new();
+
+        if !self.compose_files.is_empty() {
+            overrides.insert(String::from("compose_files"), self.compose_files.into());
+        }
+
+        if let Some(name) = self.project_name {
+            overrides.insert(String::from("project_name"), name.into());
+        }
+
+        if !self.env_files.is_empty() {
+            overrides.insert(String::from("env_files"), self.env_files.into());
+        }
+
+        if let Some(directory) = self.working_directory {
+            overrides.insert(String::from("working_directory"), directory.into());
+        }
+
+        overrides
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum DockerAction {
+    /// docker compose down, then up
+    #[value(alias = "r")]
+    Reset,
+    /// docker compose down
+    #[value(alias = "d")]
+    Down,
+    /// docker compose up
+    #[value(alias = "u")]
+    Up,
+    /// print the logs of the services
+    #[value(alias = "l")]
+    Logs,
+    /// table of the services with their state, health and published ports
+    #[value(alias = "status")]
+    Ps,
+    /// run a command in a service, an interactive shell by default
+    #[value(alias = "e")]
+    Exec,
+    /// remove the containers, volumes and orphans of the project, then bring it back up
+    Nuke,
+}
+
+#[derive(Clone, ValueEnum)]
+enum RepositoryAction {
+    /// stash, pull and pop the changes
+    #[value(alias = "r")]
+    Reset,
+    /// commit the staged changes with the message given by `--args`
+    #[value(alias = "c")]
+    Commit,
+    /// push the current branch
+    #[value(alias = "ps")]
+    Push,
+    /// pull the current branch, keeping the uncommitted changes
+    #[value(alias = "pl")]
+    Pull,
+    /// update the default branch, then rebase or merge the current one onto it
+    #[value(alias = "s")]
+    Sync,
+}
+
+#[derive(Clone, ValueEnum)]
+enum ConfigAction {
+    /// print a single config value
+    Get,
+    /// write a config value
+    Set,
+    /// remove a config value
+    Unset,
+    /// print every effective config value
+    #[value(alias = "ls")]
+    List,
+    /// open the config file with $EDITOR
+    Edit,
+    /// check every config file for unknown keys and invalid values
+    Validate,
+}
+
+impl From<DockerAction> for Action {
+    fn from(action: DockerAction) -> Self {
+        match action {
+            DockerAction::Reset => Action::Reset,
+            DockerAction::Down => Action::Down,
+            DockerAction::Up => Action::Up,
+            DockerAction::Logs => Action::Logs,
+            DockerAction::Ps => Action::Ps,
+            DockerAction::Exec => Action::Exec,
+            DockerAction::Nuke => Action::Nuke,
+        }
+    }
+}
+
+impl From<RepositoryAction> for Action {
+    fn from(action: RepositoryAction) -> Self {
+        match action {
+            RepositoryAction::Reset => Action::Reset,
+            RepositoryAction::Commit => Action::Commit,
+            RepositoryAction::Push => Action::Push,
+            RepositoryAction::Pull => Action::Pull,
+            RepositoryAction::Sync => Action::Sync,
+        }
+    }
+}
+
+impl From<ConfigAction> for Action {
+    fn from(action: ConfigAction) -> Self {
+        match action {
+            ConfigAction::Get => Action::Get,
+            ConfigAction::Set => Action::Set,
+            ConfigAction::Unset => Action::Unset,
+            ConfigAction::List => Action::List,
+            ConfigAction::Edit => Action::Edit,
+            ConfigAction::Validate => Action::Validate,
+        }
+    }
 }
 
 pub fn parse() -> Config {
-    let cli = Cli::parse();
+    let config = config(Cli::parse()).unwrap_or_else(|error| error.exit());
 
-    let cli_target = cli.target;
-    let cli_action = cli.action.unwrap_or(String::from("reset"));
-    let path = cli
-        .config
-        .unwrap_or(String::from("$HOME/.config/laterem/config.json"));
+    // Config actions are meant to be scriptable, so their output stays bare
+    if !matches!(config.target, Target::Config) {
+        let _ = Command::new("clear").status();
 
-    let target = match cli_target.as_str() {
-        "d" | "docker" => Target::Docker,
-        "r" | "repository" => Target::Repository,
-        "rc" | "current" => Target::Repository,
+        println!(
+            "{}\t\n",
+            " SELECTED OPTIONS ".bold().on_dark_magenta().white()
+        );
 
-        _ => Target::Repository,
-    };
+        println!(" - {} {}", "Target:".dark_magenta().bold(), config.target);
+        println!(
+            " - {} {}\t\n",
+            "Action:".dark_magenta().bold(),
+            config.action
+        );
+    }
 
-    let action = match cli_action.as_str() {
-        "c" | "commit" => Action::Commit,
-        "ps" | "push" => Action::Push,
-        "pl" | "pull" => Action::Pull,
+    config
+}
 
-        "r" | "reset" => Action::Reset,
-        "d" | "down" => Action::Down,
-        "u" | "up" => Action::Up,
+fn config(cli: Cli) -> Result<Config, clap::Error> {
+    let mut overrides = Map::new();
+    let mut logs = LogOptions::default();
+    let mut nuke = NukeOptions::default();
 
-        _ => Action::Reset,
-    };
+    let (target, action, values, rebase) = match cli.target {
+        TargetCommand::Docker {
+            action,
+            services,
+            logs: log_options,
+            nuke: nuke_options,
+            project,
+        } => {
+            let given = log_options.follow || log_options.since.is_some();
+            if given && !matches!(action, DockerAction::Logs) {
+                return Err(Cli::command().error(
+                    ErrorKind::ArgumentConflict,
+                    "`--follow` and `--since` only apply to `logs`",
+                ));
+            }
 
-    Command::new("clear").status().unwrap();
+            let action = nuke_action(action, &nuke_options)?;
 
-    println!(
-        "{}\t\n",
-        " SELECTED OPTIONS ".bold().on_dark_magenta().white()
-    );
+            overrides = project.overrides();
+            logs = log_options;
+            nuke = nuke_options;
 
-    println!(" - {} {}", "Target:".dark_magenta().bold(), target);
-    println!(" - {} {}\t\n", "Action:".dark_magenta().bold(), action);
+            (Target::Docker, action, services, false)
+        }
+        TargetCommand::Repository { action, rebase } => (
+            Target::Repository,
+            rebase_action(action, rebase)?,
+            Vec::new(),
+            rebase,
+        ),
+        TargetCommand::Current { action, rebase } => (
+            Target::RepositoryCurrent,
+            rebase_action(action, rebase)?,
+            Vec::new(),
+            rebase,
+        ),
+        TargetCommand::Config { .. } if cli.dry_run => {
+            return Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                "`--dry-run` doesn't apply to `config`",
+            ));
+        }
+        TargetCommand::Config { action, values } => (Target::Config, action.into(), values, false),
+    };
 
-    Config {
-        path,
+    Ok(Config {
+        path: paths::config_path(cli.config),
         target,
         action,
         defaults: None,
         arguments: cli.arguments,
+        values,
+        local: cli.local,
+        profile: cli.profile,
+        interactive: !cli.non_interactive,
+        show_origin: cli.show_origin,
+        dry_run: cli.dry_run,
+        rebase,
+        follow: logs.follow,
+        since: logs.since,
+        no_cache: nuke.no_cache,
+        yes: nuke.yes,
+        overrides,
+        origins: BTreeMap::new(),
+    })
+}
+
+/// `reset --hard` is `nuke`, and only `nuke` takes `--no-cache` and `--yes`
+fn nuke_action(action: DockerAction, options: &NukeOptions) -> Result<Action, clap::Error> {
+    let conflict = |message: &str| Cli::command().error(ErrorKind::ArgumentConflict, message);
+
+    match action {
+        DockerAction::Reset if options.hard => Ok(Action::Nuke),
+        _ if options.hard => Err(conflict("`--hard` only applies to `reset`")),
+        DockerAction::Nuke => Ok(Action::Nuke),
+        _ if options.no_cache || options.yes => Err(conflict(
+            "`--no-cache` and `--yes` only apply to `nuke` and `reset --hard`",
+        )),
+        action => Ok(action.into()),
+    }
+}
+
+/// `reset --rebase` is `sync`, and the other actions don't take `--rebase`
+fn rebase_action(action: RepositoryAction, rebase: bool) -> Result<Action, clap::Error> {
+    match action {
+        RepositoryAction::Reset if rebase => Ok(Action::Sync),
+        RepositoryAction::Reset | RepositoryAction::Sync => Ok(action.into()),
+        _ if rebase => Err(Cli::command().error(
+            ErrorKind::ArgumentConflict,
+            format!(
+                "`--rebase` only applies to `reset` and `sync`, not `{}`",
+                Action::from(action)
+            ),
+        )),
+        _ => Ok(action.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, clap::Error> {
+        Cli::try_parse_from([&["laterem"], args].concat()).and_then(config)
+    }
+
+    #[test]
+    fn aliases_select_their_target_and_action() {
+        let cases = [
+            (vec!["d"], Target::Docker, Action::Reset),
+            (vec!["docker", "u"], Target::Docker, Action::Up),
+            (vec!["d", "down"], Target::Docker, Action::Down),
+            (vec!["d", "status"], Target::Docker, Action::Ps),
+            (vec!["d", "l"], Target::Docker, Action::Logs),
+            (vec!["r"], Target::Repository, Action::Reset),
+            (vec!["repository", "pl"], Target::Repository, Action::Pull),
+            (vec!["rc", "c"], Target::RepositoryCurrent, Action::Commit),
+            (
+                vec!["current", "push"],
+                Target::RepositoryCurrent,
+                Action::Push,
+            ),
+            (vec!["r", "s"], Target::Repository, Action::Sync),
+            (
+                vec!["rc", "reset", "--rebase"],
+                Target::RepositoryCurrent,
+                Action::Sync,
+            ),
+            (vec!["cfg"], Target::Config, Action::List),
+            (vec!["config", "ls"], Target::Config, Action::List),
+        ];
+
+        for (args, target, action) in cases {
+            let config = parse(&args).unwrap();
+
+            assert_eq!(config.target.to_string(), target.to_string(), "{:?}", args);
+            assert_eq!(config.action.to_string(), action.to_string(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn unknown_target_is_rejected() {
+        let error = parse(&["dokcer"]).err().unwrap();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidSubcommand);
+        assert!(error.to_string().contains("docker"));
+    }
+
+    #[test]
+    fn unknown_action_lists_the_possible_ones() {
+        let error = parse(&["d", "rest"]).err().unwrap();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidValue);
+        assert!(error.to_string().contains("reset"));
+    }
+
+    #[test]
+    fn actions_of_other_targets_are_rejected() {
+        for args in [["docker", "commit"], ["r", "up"], ["config", "push"]] {
+            let error = parse(&args).err().unwrap();
+
+            assert_eq!(error.kind(), ErrorKind::InvalidValue, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn rebase_only_applies_to_reset_and_sync() {
+        assert!(parse(&["r", "sync", "--rebase"]).unwrap().rebase);
+
+        let error = parse(&["r", "push", "--rebase"]).err().unwrap();
+
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn options_are_accepted_after_the_target() {
+        let config = parse(&["r", "c", "--args", "fix: bug fix", "--dry-run"]).unwrap();
+
+        assert_eq!(config.arguments, ["fix: bug fix"]);
+        assert!(config.dry_run);
+    }
+
+    #[test]
+    fn compose_flags_override_the_config_files() {
+        let config = parse(&[
+            "d",
+            "up",
+            "-f",
+            "compose.yml",
+            "-f",
+            "compose.dev.yml",
+            "--project-name",
+            "api",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            Value::Object(config.overrides),
+            serde_json::json!({
+                "compose_files": ["compose.yml", "compose.dev.yml"],
+                "project_name": "api",
+            })
+        );
+    }
+
+    #[test]
+    fn docker_services_are_kept() {
+        let config = parse(&["d", "reset", "api", "worker"]).unwrap();
+
+        assert_eq!(config.values, ["api", "worker"]);
+    }
+
+    #[test]
+    fn logs_options_only_apply_to_logs() {
+        let config = parse(&["d", "logs", "api", "--follow", "--since", "10m"]).unwrap();
+
+        assert!(config.follow);
+        assert_eq!(config.since.as_deref(), Some("10m"));
+
+        let error = parse(&["d", "up", "--follow"]).err().unwrap();
+
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn exec_takes_the_command_after_the_service() {
+        let config = parse(&["d", "exec", "api", "--", "ls", "-la"]).unwrap();
+
+        assert_eq!(config.values, ["api", "ls", "-la"]);
+    }
+
+    #[test]
+    fn hard_reset_is_a_nuke() {
+        let config = parse(&["d", "reset", "--hard", "--no-cache", "-y"]).unwrap();
+
+        assert_eq!(config.action.to_string(), "nuke");
+        assert!(config.no_cache && config.yes);
+
+        for args in [&["d", "up", "--hard"][..], &["d", "reset", "--yes"]] {
+            let error = parse(args).err().unwrap();
+
+            assert_eq!(error.kind(), ErrorKind::ArgumentConflict, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn config_rejects_dry_run() {
+        let error = parse(&["config", "set", "branch", "main", "--dry-run"])
+            .err()
+            .unwrap();
+
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn config_values_are_kept() {
+        let config = parse(&["config", "set", "branch", "develop", "--local"]).unwrap();
+
+        assert_eq!(config.values, ["branch", "develop"]);
+        assert!(config.local);
     }
 }
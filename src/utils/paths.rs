@@ -0,0 +1,204 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// Environment variable that overrides the default config location
+pub const CONFIG_ENV: &str = "LATEREM_CONFIG";
+
+/// Value of an environment variable, the process environment outside of the tests
+type Variables<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// Resolve the config file location, in order: `--config`, `$LATEREM_CONFIG`,
+/// `$XDG_CONFIG_HOME/laterem/config.json` and `~/.config/laterem/config.json`
+pub fn config_path(cli: Option<String>) -> PathBuf {
+    resolve(cli, &|name| env::var(name).ok())
+}
+
+fn resolve(cli: Option<String>, variables: Variables) -> PathBuf {
+    if let Some(path) = cli.filter(|path| !path.is_empty()) {
+        return absolute(expand(&path, variables));
+    }
+
+    if let Some(path) = non_empty_var(CONFIG_ENV, variables) {
+        return absolute(expand(&path, variables));
+    }
+
+    let base = non_empty_var("XDG_CONFIG_HOME", variables)
+        .map(|path| expand(&path, variables))
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home(variables).join(".config"));
+
+    absolute(base.join("laterem").join("config.json"))
+}
+
+/// Expand a leading `~` and every `$VAR` or `${VAR}` reference, unset variables expand to nothing
+fn expand(raw: &str, variables: Variables) -> PathBuf {
+    let raw = match raw.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home(variables).display(), rest)
+        }
+        _ => raw.to_string(),
+    };
+
+    let mut expanded = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        if char != '$' {
+            expanded.push(char);
+            continue;
+        }
+
+        let braced = chars.next_if_eq(&'{').is_some();
+        let mut name = String::new();
+
+        while let Some(next) = chars.next_if(|next| next.is_ascii_alphanumeric() || *next == '_') {
+            name.push(next);
+        }
+
+        if braced && chars.next_if_eq(&'}').is_none() {
+            expanded.push_str("${");
+            expanded.push_str(&name);
+            continue;
+        }
+
+        if name.is_empty() {
+            expanded.push('$');
+            if braced {
+                expanded.push_str("{}");
+            }
+            continue;
+        }
+
+        expanded.push_str(&variables(&name).unwrap_or_default());
+    }
+
+    PathBuf::from(expanded)
+}
+
+fn absolute(path: PathBuf) -> PathBuf {
+    if path.is_absolute() {
+        return path;
+    }
+
+    env::current_dir()
+        .map(|current| current.join(&path))
+        .unwrap_or(path)
+}
+
+fn home(variables: Variables) -> PathBuf {
+    non_empty_var("HOME", variables)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new("/").to_path_buf())
+}
+
+fn non_empty_var(name: &str, variables: Variables) -> Option<String> {
+    variables(name).filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// A lookup answering from `pairs` only, the process environment is left alone
+    fn variables(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let pairs: HashMap<String, String> = pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        move |name| pairs.get(name).cloned()
+    }
+
+    #[test]
+    fn expands_the_home_directory() {
+        let home = variables(&[("HOME", "/home/user")]);
+
+        assert_eq!(expand("~", &home), Path::new("/home/user"));
+        assert_eq!(
+            expand("~/config.json", &home),
+            Path::new("/home/user/config.json")
+        );
+        assert_eq!(
+            expand("~user/config.json", &home),
+            Path::new("~user/config.json")
+        );
+        assert_eq!(expand("a/~/b", &home), Path::new("a/~/b"));
+        assert_eq!(expand("~/a", &variables(&[])), Path::new("//a"));
+    }
+
+    #[test]
+    fn expands_variables() {
+        let set = variables(&[("NAME", "value")]);
+
+        assert_eq!(expand("$NAME/a", &set), Path::new("value/a"));
+        assert_eq!(expand("${NAME}a", &set), Path::new("valuea"));
+        assert_eq!(expand("a/$UNSET/b", &set), Path::new("a//b"));
+        assert_eq!(expand("a/${UNSET}", &set), Path::new("a/"));
+    }
+
+    #[test]
+    fn keeps_what_isnt_a_variable() {
+        let set = variables(&[("HOME", "/home/user")]);
+
+        assert_eq!(expand("a/$", &set), Path::new("a/$"));
+        assert_eq!(expand("$/a", &set), Path::new("$/a"));
+        assert_eq!(expand("a/${}", &set), Path::new("a/${}"));
+        assert_eq!(expand("a/${HOME", &set), Path::new("a/${HOME"));
+        assert_eq!(expand("a/${HOME/b", &set), Path::new("a/${HOME/b"));
+    }
+
+    #[test]
+    fn config_path_order() {
+        let all = variables(&[
+            ("HOME", "/home/user"),
+            ("XDG_CONFIG_HOME", "/xdg"),
+            (CONFIG_ENV, "/variable.json"),
+        ]);
+
+        assert_eq!(
+            resolve(Some(String::from("/etc/laterem.json")), &all),
+            Path::new("/etc/laterem.json")
+        );
+        assert_eq!(
+            resolve(Some(String::new()), &all),
+            Path::new("/variable.json")
+        );
+        assert_eq!(resolve(None, &all), Path::new("/variable.json"));
+
+        let xdg = variables(&[
+            ("HOME", "/home/user"),
+            ("XDG_CONFIG_HOME", "/xdg"),
+            (CONFIG_ENV, ""),
+        ]);
+        assert_eq!(resolve(None, &xdg), Path::new("/xdg/laterem/config.json"));
+
+        let fallback = Path::new("/home/user/.config/laterem/config.json");
+        let relative = variables(&[("HOME", "/home/user"), ("XDG_CONFIG_HOME", "relative")]);
+        assert_eq!(resolve(None, &relative), fallback);
+        assert_eq!(
+            resolve(None, &variables(&[("HOME", "/home/user")])),
+            fallback
+        );
+    }
+
+    #[test]
+    fn relative_paths_are_made_absolute() {
+        let current = env::current_dir().unwrap();
+
+        assert_eq!(
+            resolve(Some(String::from("config.json")), &variables(&[])),
+            current.join("config.json")
+        );
+        assert_eq!(
+            resolve(
+                Some(String::from("$DIR/c.json")),
+                &variables(&[("DIR", "a")])
+            ),
+            current.join("a/c.json")
+        );
+    }
+}
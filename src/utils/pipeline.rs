@@ -0,0 +1,309 @@
+use crossterm::style::Stylize;
+
+use super::{
+    entities::LateremError,
+    runner::{self, CommandRunner},
+};
+
+/// What the engine does when the command of a step fails
+pub enum OnFailure {
+    /// stop, leaving the remaining steps unrun
+    Abort,
+    /// warn and carry on with the next step
+    Continue,
+    /// stop without rolling back, leaving the repository as the step left it for the user to
+    /// finish, e.g. a rebase stopped on conflicts
+    Halt,
+}
+
+/// Finds the arguments of a step right before it runs, `None` skipping it
+pub type Resolve = Box<dyn Fn(&mut dyn CommandRunner) -> Result<Option<Vec<String>>, LateremError>>;
+
+/// A single command of an action, alongside the message shown while it runs
+pub struct Step {
+    pub description: String,
+    /// highlighted after the description, usually a branch
+    pub subject: Option<String>,
+    pub program: &'static str,
+    pub args: Vec<String>,
+    pub on_failure: OnFailure,
+    /// reverts the step once it succeeded
+    pub undo: Option<Box<Step>>,
+    /// replaces `args` when the step depends on the state left by the previous ones, a dry run
+    /// prints `args` instead
+    pub resolve: Option<Resolve>,
+    /// runs attached to the terminal, e.g. a shell, see [`CommandRunner::attach`]
+    pub interactive: bool,
+}
+
+impl Step {
+    pub fn new(program: &'static str, description: &str, args: &[&str]) -> Self {
+        Self {
+            description: description.to_string(),
+            subject: None,
+            program,
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            on_failure: OnFailure::Abort,
+            undo: None,
+            resolve: None,
+            interactive: false,
+        }
+    }
+
+    pub fn git(description: &str, args: &[&str]) -> Self {
+        Step::new("git", description, args)
+    }
+
+    pub fn docker(description: &str, args: &[&str]) -> Self {
+        Step::new("docker", description, args)
+    }
+
+    pub fn on(mut self, subject: &str) -> Self {
+        self.subject = Some(subject.to_string());
+        self
+    }
+
+    /// Keep running the next steps when this one fails
+    pub fn optional(mut self) -> Self {
+        self.on_failure = OnFailure::Continue;
+        self
+    }
+
+    /// Stop without rolling back when this step fails
+    pub fn halting(mut self) -> Self {
+        self.on_failure = OnFailure::Halt;
+        self
+    }
+
+    /// Run attached to the terminal, its exit status being passed through as is
+    pub fn interactive(mut self) -> Self {
+        self.interactive = true;
+        self
+    }
+
+    pub fn undo(mut self, step: Step) -> Self {
+        self.undo = Some(Box::new(step));
+        self
+    }
+
+    pub fn resolved(
+        mut self,
+        resolve: impl Fn(&mut dyn CommandRunner) -> Result<Option<Vec<String>>, LateremError> + 'static,
+    ) -> Self {
+        self.resolve = Some(Box::new(resolve));
+        self
+    }
+
+    fn command(&self) -> String {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+
+        runner::quote(self.program, &args)
+    }
+
+    fn announce(&self, first: bool) {
+        let separator = if first { "" } else { "\t\n" };
+        let subject = match &self.subject {
+            Some(subject) => format!(" {}", subject.clone().magenta()),
+            None => String::new(),
+        };
+
+        println!(
+            "{}{}{}{}{}{}",
+            separator,
+            self.description.clone().dim(),
+            subject,
+            ".".rapid_blink(),
+            ".".rapid_blink(),
+            ".".dim(),
+        );
+    }
+}
+
+/// Run every step in order, stopping at the first failure of a step that can't be skipped and
+/// undoing the steps that already ran
+pub fn run(steps: &[Step], runner: &mut dyn CommandRunner) -> Result<(), LateremError> {
+    let mut undo: Vec<&Step> = Vec::new();
+
+    for (index, step) in steps.iter().enumerate() {
+        step.announce(index == 0);
+
+        // A step running the undo of an earlier one takes over reverting it, so a failure
+        // doesn't make the rollback repeat it
+        let command = step.command();
+        undo.retain(|pending| pending.command() != command);
+
+        match execute(step, runner) {
+            Ok(true) => undo.extend(step.undo.as_deref()),
+            Ok(false) => {}
+            Err(error) => match step.on_failure {
+                OnFailure::Continue => {
+                    println!(" {} {}", "Warning:".dark_yellow().bold(), error);
+                }
+                OnFailure::Abort => {
+                    rollback(&undo, runner);
+
+                    return Err(error);
+                }
+                OnFailure::Halt => return Err(error),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the command of `step`, returning `false` when it resolved to nothing to do
+fn execute(step: &Step, runner: &mut dyn CommandRunner) -> Result<bool, LateremError> {
+    let args = match &step.resolve {
+        Some(resolve) if !runner.dry_run() => match resolve(runner)? {
+            Some(args) => args,
+            None => {
+                println!(" {}", "Nothing to do, skipped".dim());
+                return Ok(false);
+            }
+        },
+        _ => step.args.clone(),
+    };
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match step.interactive {
+        true => runner.attach(step.program, &args)?,
+        false => runner.execute(step.program, &args)?,
+    }
+
+    Ok(true)
+}
+
+/// Run the undo of every completed step, latest first, and report what was restored
+fn rollback(undo: &[&Step], runner: &mut dyn CommandRunner) {
+    if undo.is_empty() {
+        return;
+    }
+
+    println!(
+        "\t\n{}\t\n",
+        " ROLLING BACK ".on_dark_yellow().white().bold()
+    );
+
+    let mut report = Vec::new();
+
+    for (index, step) in undo.iter().rev().enumerate() {
+        step.announce(index == 0);
+        report.push((step.command(), execute(step, runner)));
+    }
+
+    println!();
+
+    for (command, result) in report {
+        match result {
+            Ok(false) => {}
+            Ok(true) => println!(
+                " - {} {}",
+                "Restored:".dark_magenta().bold(),
+                command.magenta()
+            ),
+            Err(error) => println!(
+                " - {} {} {}",
+                "Couldn't restore, run it manually:".dark_red().bold(),
+                command.magenta(),
+                format!("({})", error).dim(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::runner::RecordingRunner;
+
+    fn steps() -> Vec<Step> {
+        vec![
+            Step::git("Fetching", &["fetch"]).optional(),
+            Step::git("Stashing", &["stash"]).undo(Step::git("Popping", &["stash", "pop"])),
+            Step::git("Pulling", &["pull"]),
+            Step::git("Popping", &["stash", "pop"]),
+        ]
+    }
+
+    #[test]
+    fn every_step_runs_in_order() {
+        let mut runner = RecordingRunner::new();
+
+        assert!(run(&steps(), &mut runner).is_ok());
+        assert_eq!(
+            runner.calls,
+            ["git fetch", "git stash", "git pull", "git stash pop"]
+        );
+    }
+
+    #[test]
+    fn optional_step_failure_is_skipped() {
+        let mut runner = RecordingRunner::new().failing("git fetch");
+
+        assert!(run(&steps(), &mut runner).is_ok());
+        assert_eq!(runner.calls.len(), 4);
+    }
+
+    #[test]
+    fn failure_rolls_back_the_completed_steps() {
+        let mut runner = RecordingRunner::new().failing("git pull");
+
+        assert!(run(&steps(), &mut runner).is_err());
+        assert_eq!(
+            runner.calls,
+            ["git fetch", "git stash", "git pull", "git stash pop"]
+        );
+    }
+
+    #[test]
+    fn step_running_an_undo_is_not_rolled_back_again() {
+        let mut runner = RecordingRunner::new().failing("git stash pop");
+
+        assert!(run(&steps(), &mut runner).is_err());
+        assert_eq!(
+            runner.calls,
+            ["git fetch", "git stash", "git pull", "git stash pop"]
+        );
+    }
+
+    #[test]
+    fn halting_step_failure_skips_the_rollback() {
+        let steps = vec![
+            Step::git("Stashing", &["stash"]).undo(Step::git("Popping", &["stash", "pop"])),
+            Step::git("Rebasing", &["rebase", "main"]).halting(),
+            Step::git("Popping", &["stash", "pop"]),
+        ];
+        let mut runner = RecordingRunner::new().failing("git rebase main");
+
+        assert!(run(&steps, &mut runner).is_err());
+        assert_eq!(runner.calls, ["git stash", "git rebase main"]);
+    }
+
+    #[test]
+    fn resolved_step_runs_the_resolved_arguments() {
+        let steps = vec![
+            Step::git("Popping", &["stash", "pop"])
+                .resolved(|_| Ok(Some(vec![String::from("stash"), String::from("drop")]))),
+            Step::git("Popping", &["stash", "pop"]).resolved(|_| Ok(None)),
+        ];
+        let mut runner = RecordingRunner::new();
+
+        assert!(run(&steps, &mut runner).is_ok());
+        assert_eq!(runner.calls, ["git stash drop"]);
+    }
+
+    #[test]
+    fn rollback_runs_the_latest_undo_first() {
+        let steps = vec![
+            Step::git("Stashing", &["stash"]).undo(Step::git("Popping", &["stash", "pop"])),
+            Step::git("Checking out", &["checkout", "main"])
+                .undo(Step::git("Going back", &["checkout", "feature"])),
+            Step::git("Pulling", &["pull"]),
+        ];
+        let mut runner = RecordingRunner::new().failing("git pull");
+
+        assert!(run(&steps, &mut runner).is_err());
+        assert_eq!(runner.calls[3..], ["git checkout feature", "git stash pop"]);
+    }
+}
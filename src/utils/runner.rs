@@ -0,0 +1,389 @@
+use std::{
+    io::{self, Read, Write},
+    os::unix::process::ExitStatusExt,
+    process::{Command, ExitStatus, Stdio},
+};
+
+use crossterm::style::Stylize;
+
+use super::{
+    entities::LateremError,
+    git::{self, Status},
+};
+
+/// Every git and docker invocation made by `Action::run` goes through a runner, and so does
+/// every read of the repository
+pub trait CommandRunner {
+    /// Run a step, its stdout going straight to the terminal
+    fn execute(&mut self, program: &str, args: &[&str]) -> Result<(), LateremError>;
+
+    /// Run a step attached to the terminal, e.g. a shell, its exit status being the user's
+    fn attach(&mut self, program: &str, args: &[&str]) -> Result<(), LateremError> {
+        self.execute(program, args)
+    }
+
+    /// Run a query that doesn't change anything and return its trimmed stdout
+    fn capture(&mut self, program: &str, args: &[&str]) -> Result<String, LateremError>;
+
+    /// Whether steps are only printed, so they can't depend on the state left by earlier ones
+    fn dry_run(&self) -> bool {
+        false
+    }
+
+    /// State of the repository containing the current directory
+    fn status(&mut self) -> Result<Status, LateremError>;
+
+    /// Reference of the newest stash entry named `message`, e.g. `stash@{1}`
+    fn stash_reference(&mut self, message: &str) -> Result<Option<String>, LateremError>;
+}
+
+/// Spawns every command as a child process
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn execute(&mut self, program: &str, args: &[&str]) -> Result<(), LateremError> {
+        execute(Command::new(program).args(args))
+    }
+
+    fn attach(&mut self, program: &str, args: &[&str]) -> Result<(), LateremError> {
+        attach(Command::new(program).args(args))
+    }
+
+    fn capture(&mut self, program: &str, args: &[&str]) -> Result<String, LateremError> {
+        capture(Command::new(program).args(args))
+    }
+
+    fn status(&mut self) -> Result<Status, LateremError> {
+        git::status(&git::open()?)
+    }
+
+    fn stash_reference(&mut self, message: &str) -> Result<Option<String>, LateremError> {
+        git::stash_reference(&mut git::open()?, message)
+    }
+}
+
+/// Prints every step instead of running it, the repository is still read and queries still run
+/// so the plan shows resolved values
+pub struct DryRunRunner;
+
+impl CommandRunner for DryRunRunner {
+    fn execute(&mut self, program: &str, args: &[&str]) -> Result<(), LateremError> {
+        println!(" {} {}", "$".dim(), quote(program, args).magenta());
+
+        Ok(())
+    }
+
+    fn capture(&mut self, program: &str, args: &[&str]) -> Result<String, LateremError> {
+        SystemRunner.capture(program, args)
+    }
+
+    fn dry_run(&self) -> bool {
+        true
+    }
+
+    fn status(&mut self) -> Result<Status, LateremError> {
+        SystemRunner.status()
+    }
+
+    fn stash_reference(&mut self, message: &str) -> Result<Option<String>, LateremError> {
+        SystemRunner.stash_reference(message)
+    }
+}
+
+/// A command line that can be pasted back into a shell
+pub fn quote(program: &str, args: &[&str]) -> String {
+    [program]
+        .iter()
+        .chain(args)
+        .map(|part| {
+            if part.is_empty() || part.contains(|c: char| c.is_whitespace() || "'\"$*".contains(c))
+            {
+                format!("'{}'", part.replace('\'', r"'\''"))
+            } else {
+                part.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn describe(command: &Command) -> String {
+    [command.get_program()]
+        .into_iter()
+        .chain(command.get_args())
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn failure(command: String, status: ExitStatus, stderr: &[u8]) -> LateremError {
+    let stderr = String::from_utf8_lossy(stderr).trim().to_string();
+
+    if command.starts_with("git ") && stderr.contains("not a git repository") {
+        return LateremError::NotGitRepository;
+    }
+
+    LateremError::Failed {
+        command,
+        status,
+        stderr,
+    }
+}
+
+/// Run `command` to completion, forwarding its stderr while keeping a copy for the error
+fn execute(command: &mut Command) -> Result<(), LateremError> {
+    let line = describe(command);
+    let spawn = |source| LateremError::Spawn {
+        command: line.clone(),
+        source,
+    };
+
+    let mut child = command.stderr(Stdio::piped()).spawn().map_err(spawn)?;
+    let mut stderr = Vec::new();
+
+    if let Some(mut pipe) = child.stderr.take() {
+        let mut buffer = [0; 1024];
+
+        loop {
+            match pipe.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => {
+                    let _ = io::stderr().write_all(&buffer[..read]);
+                    stderr.extend_from_slice(&buffer[..read]);
+                }
+            }
+        }
+    }
+
+    let status = child.wait().map_err(spawn)?;
+
+    if status.success() {
+        return Ok(());
+    }
+
+    Err(failure(line, status, &stderr))
+}
+
+/// Run `command` with the stdin, stdout and stderr of laterem, a non-zero exit being passed
+/// through instead of reported as a failure
+fn attach(command: &mut Command) -> Result<(), LateremError> {
+    let line = describe(command);
+    let status = command.status().map_err(|source| LateremError::Spawn {
+        command: line.clone(),
+        source,
+    })?;
+
+    if status.success() {
+        return Ok(());
+    }
+
+    // A command killed by a signal exits like a shell reports it
+    let code = status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1);
+
+    Err(LateremError::Exited {
+        command: line,
+        code: u8::try_from(code).unwrap_or(1),
+    })
+}
+
+/// Run `command` and return its trimmed stdout
+fn capture(command: &mut Command) -> Result<String, LateremError> {
+    let line = describe(command);
+    let output = command.output().map_err(|source| LateremError::Spawn {
+        command: line.clone(),
+        source,
+    })?;
+
+    if !output.status.success() {
+        return Err(failure(line, output.status, &output.stderr));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Records every invocation instead of running it, answering queries from `outputs` and reads
+/// of the repository from `branch`, `clean` and `stashes`
+#[cfg(test)]
+#[derive(Default)]
+pub struct RecordingRunner {
+    pub calls: Vec<String>,
+    pub outputs: std::collections::HashMap<String, String>,
+    pub failing: Option<String>,
+    pub branch: String,
+    pub clean: bool,
+    pub stashes: Vec<String>,
+    pub operation: Option<&'static str>,
+}
+
+#[cfg(test)]
+impl RecordingRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `command` with `output` when it's captured
+    pub fn output(mut self, command: &str, output: &str) -> Self {
+        self.outputs.insert(command.to_string(), output.to_string());
+        self
+    }
+
+    pub fn branch(mut self, branch: &str) -> Self {
+        self.branch = branch.to_string();
+        self
+    }
+
+    /// Report a tree without uncommitted changes
+    pub fn clean(mut self) -> Self {
+        self.clean = true;
+        self
+    }
+
+    /// Report `operation` as stopped once `command` fails
+    pub fn conflicting(mut self, command: &str, operation: &'static str) -> Self {
+        self.failing = Some(command.to_string());
+        self.operation = Some(operation);
+        self
+    }
+
+    /// Add a stash entry named `message` on top of the others
+    pub fn stash(mut self, message: &str) -> Self {
+        self.stashes.insert(0, message.to_string());
+        self
+    }
+
+    /// Make `command` exit with a non-zero status
+    pub fn failing(mut self, command: &str) -> Self {
+        self.failing = Some(command.to_string());
+        self
+    }
+
+    fn record(&mut self, program: &str, args: &[&str]) -> Result<String, LateremError> {
+        let line = [program]
+            .iter()
+            .chain(args)
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.calls.push(line.clone());
+
+        if self.failing.as_ref() == Some(&line) {
+            return Err(LateremError::Failed {
+                command: line,
+                status: ExitStatus::from_raw(1 << 8),
+                stderr: String::new(),
+            });
+        }
+
+        Ok(self.outputs.get(&line).cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for RecordingRunner {
+    fn execute(&mut self, program: &str, args: &[&str]) -> Result<(), LateremError> {
+        self.record(program, args).map(|_| ())
+    }
+
+    fn capture(&mut self, program: &str, args: &[&str]) -> Result<String, LateremError> {
+        self.record(program, args)
+    }
+
+    fn status(&mut self) -> Result<Status, LateremError> {
+        Ok(Status {
+            branch: self.branch.clone(),
+            dirty: !self.clean,
+            upstream: None,
+            operation: self.operation.filter(|_| {
+                self.failing
+                    .as_ref()
+                    .is_some_and(|failing| self.calls.contains(failing))
+            }),
+        })
+    }
+
+    fn stash_reference(&mut self, message: &str) -> Result<Option<String>, LateremError> {
+        let index = self.stashes.iter().position(|stash| stash == message);
+
+        Ok(index.map(|index| format!("stash@{{{}}}", index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_git_command_exits_with_the_git_code() {
+        let error = execute(Command::new("git").arg("--no-such-flag")).unwrap_err();
+
+        assert!(matches!(error, LateremError::Failed { .. }));
+        assert!(error.to_string().contains("git --no-such-flag"));
+        assert_eq!(error.exit_code(), 4);
+    }
+
+    #[test]
+    fn failed_command_keeps_its_stderr() {
+        let error =
+            execute(Command::new("sh").args(["-c", "echo broken >&2; exit 7"])).unwrap_err();
+
+        assert!(error.to_string().ends_with(": broken"));
+        assert_eq!(error.exit_code(), 1);
+    }
+
+    #[test]
+    fn missing_program_is_a_spawn_failure() {
+        let error = execute(&mut Command::new("laterem-no-such-program")).unwrap_err();
+
+        assert!(matches!(error, LateremError::Spawn { .. }));
+        assert!(error.hint().contains("laterem-no-such-program"));
+    }
+
+    #[test]
+    fn git_outside_a_repository_is_reported() {
+        let directory = tempfile::tempdir().unwrap();
+        let error = execute(
+            Command::new("git")
+                .args(["status"])
+                .current_dir(directory.path())
+                .env("GIT_CEILING_DIRECTORIES", directory.path()),
+        )
+        .unwrap_err();
+
+        assert!(matches!(error, LateremError::NotGitRepository));
+        assert_eq!(error.exit_code(), 4);
+    }
+
+    #[test]
+    fn dry_run_lines_can_be_pasted_into_a_shell() {
+        assert_eq!(quote("git", &["checkout", "main"]), "git checkout main");
+        assert_eq!(
+            quote("git", &["commit", "-m", "fix: don't panic"]),
+            r"git commit -m 'fix: don'\''t panic'"
+        );
+        assert_eq!(quote("git", &["add", "*"]), "git add '*'");
+    }
+
+    #[test]
+    fn attached_command_passes_its_exit_code_through() {
+        let error = attach(Command::new("sh").args(["-c", "exit 3"])).unwrap_err();
+
+        assert!(matches!(error, LateremError::Exited { code: 3, .. }));
+        assert_eq!(error.exit_code(), 3);
+
+        let error = attach(Command::new("sh").args(["-c", "kill -TERM $$"])).unwrap_err();
+
+        assert_eq!(error.exit_code(), 143);
+        assert!(attach(&mut Command::new("true")).is_ok());
+    }
+
+    #[test]
+    fn successful_command_is_ok() {
+        assert!(execute(Command::new("git").arg("--version")).is_ok());
+        assert!(capture(Command::new("git").arg("--version"))
+            .unwrap()
+            .starts_with("git version"));
+    }
+}
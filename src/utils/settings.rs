@@ -0,0 +1,441 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crossterm::style::Stylize;
+use serde_json::{Map, Value};
+
+use super::{
+    entities::{Action, Config, DefaultConfig, LateremError},
+    layers::{self, Layer, Origin, DEFAULT_PROFILE_KEY, REPOSITORY_FILE},
+};
+
+/// Run a `config` action against the global file, or the repository file with `--local`, writes
+/// go to the profile given by `--profile` when there's one
+pub fn run(config: &mut Config) -> Result<(), LateremError> {
+    match &config.action {
+        Action::Get => {
+            let key = known_key(config.values.first())?;
+
+            config.load_read_only()?;
+
+            let value = config
+                .defaults
+                .as_ref()
+                .and_then(|defaults| defaults.values().remove(&key))
+                .unwrap_or(Value::Null);
+
+            match value {
+                Value::String(value) => println!("{}", value),
+                value => println!("{}", value),
+            }
+
+            Ok(())
+        }
+        Action::Set if is_default_profile(config) => {
+            let Some(name) = config.values.get(1) else {
+                return Err(LateremError::InvalidArgument(format!(
+                    "missing the profile to assign to `{}`",
+                    DEFAULT_PROFILE_KEY
+                )));
+            };
+
+            default_profile(config, Some(name.clone()))
+        }
+        Action::Unset if is_default_profile(config) => default_profile(config, None),
+        Action::Set => {
+            let key = known_key(config.values.first())?;
+            let Some(raw) = config.values.get(1) else {
+                return Err(LateremError::InvalidArgument(format!(
+                    "missing the value to assign to `{}`",
+                    key
+                )));
+            };
+
+            let path = target_file(config);
+            let mut file = read(&path, file_origin(config, &path))?;
+            let values = scope(&mut file, config.profile.as_deref());
+
+            let value = layers::parse_value(&key, raw);
+            values.insert(key.clone(), value.clone());
+
+            DefaultConfig::check(values).map_err(|error| {
+                LateremError::InvalidArgument(format!("invalid value for `{}`: {}", key, error))
+            })?;
+            layers::write(&path, &file.document())?;
+
+            println!(
+                " - {} {} {}",
+                format!("{}:", key).dark_magenta().bold(),
+                value,
+                format!("({})", path.display()).dim(),
+            );
+
+            Ok(())
+        }
+        Action::Unset => {
+            let key = known_key(config.values.first())?;
+
+            let path = target_file(config);
+            let mut file = read(&path, file_origin(config, &path))?;
+
+            if scope(&mut file, config.profile.as_deref())
+                .remove(&key)
+                .is_none()
+            {
+                println!(
+                    " {} {}",
+                    format!("`{}` isn't set in", key).dim(),
+                    path.display()
+                );
+
+                return Ok(());
+            }
+
+            layers::write(&path, &file.document())?;
+
+            println!(
+                " {} {}",
+                format!("Removed `{}` from", key).dim(),
+                path.display()
+            );
+
+            Ok(())
+        }
+        Action::List => {
+            config.load_read_only()?;
+            config.print_values();
+
+            Ok(())
+        }
+        Action::Edit => {
+            let path = target_file(config);
+
+            if !path.exists() {
+                layers::write(&path, &Map::new())?;
+            }
+
+            let editor = env::var("VISUAL")
+                .or_else(|_| env::var("EDITOR"))
+                .unwrap_or(String::from("vi"));
+
+            let status = Command::new(&editor)
+                .arg(&path)
+                .status()
+                .map_err(|source| LateremError::Spawn {
+                    command: editor.clone(),
+                    source,
+                })?;
+
+            if !status.success() {
+                return Err(LateremError::Failed {
+                    command: format!("{} {}", editor, path.display()),
+                    status,
+                    stderr: String::new(),
+                });
+            }
+
+            validate(&path, file_origin(config, &path))
+        }
+        Action::Validate => {
+            let mut files = vec![config.path.clone()];
+            files.extend(
+                env::current_dir()
+                    .ok()
+                    .and_then(|current| layers::repository_file(&current)),
+            );
+
+            files
+                .iter()
+                .try_for_each(|path| validate(path, file_origin(config, path)))
+        }
+        action => Err(LateremError::InvalidArgument(format!(
+            "`{}` isn't a config action",
+            action
+        ))),
+    }
+}
+
+/// Check a single config file and every profile it declares, a missing file is valid and an
+/// outdated one is checked once migrated without being rewritten
+fn validate(path: &Path, origin: Origin) -> Result<(), LateremError> {
+    let Some(file) = Layer::read(path, origin, false)? else {
+        println!(" - {} {}", path.display(), "(missing)".dim());
+        return Ok(());
+    };
+    file.warn_outdated();
+
+    let keys = DefaultConfig::keys();
+    let scopes = [(String::new(), &file.values)].into_iter().chain(
+        file.profiles
+            .iter()
+            .map(|(name, values)| (format!(" (profile {})", name), values)),
+    );
+
+    for (scope, values) in scopes {
+        let unknown: Vec<String> = values
+            .keys()
+            .filter(|key| !keys.contains(key))
+            .map(|key| describe_unknown(key, &keys))
+            .collect();
+
+        if !unknown.is_empty() {
+            return Err(LateremError::InvalidConfig(format!(
+                "{}{}: {}",
+                path.display(),
+                scope,
+                unknown.join(", ")
+            )));
+        }
+
+        DefaultConfig::check(values).map_err(|error| {
+            LateremError::InvalidConfig(format!("{}{}: {}", path.display(), scope, error))
+        })?;
+    }
+
+    println!(" - {} {}", path.display(), "(valid)".dim());
+
+    Ok(())
+}
+
+fn is_default_profile(config: &Config) -> bool {
+    config.values.first().map(String::as_str) == Some(DEFAULT_PROFILE_KEY)
+}
+
+/// Write or remove the profile selected when neither `--profile` nor `$LATEREM_PROFILE` is set
+fn default_profile(config: &Config, name: Option<String>) -> Result<(), LateremError> {
+    let path = target_file(config);
+    let mut file = read(&path, file_origin(config, &path))?;
+
+    file.default_profile = name;
+    layers::write(&path, &file.document())?;
+
+    println!(
+        " - {} {} {}",
+        format!("{}:", DEFAULT_PROFILE_KEY).dark_magenta().bold(),
+        file.default_profile.as_deref().unwrap_or("none"),
+        format!("({})", path.display()).dim(),
+    );
+
+    Ok(())
+}
+
+/// The global config file, or the repository one with `--local`
+fn target_file(config: &Config) -> PathBuf {
+    if !config.local {
+        return config.path.clone();
+    }
+
+    let current = env::current_dir().unwrap_or_default();
+
+    layers::repository_file(&current).unwrap_or_else(|| {
+        layers::repository_root(&current)
+            .unwrap_or(&current)
+            .join(REPOSITORY_FILE)
+    })
+}
+
+/// The global origin for the file at `--config`, the repository one for any other
+fn file_origin(config: &Config, path: &Path) -> Origin {
+    match path == config.path {
+        true => Origin::Global(path.to_path_buf()),
+        false => Origin::Repository(path.to_path_buf()),
+    }
+}
+
+fn read(path: &Path, origin: Origin) -> Result<Layer, LateremError> {
+    Ok(Layer::read(path, origin.clone(), true)?.unwrap_or_else(|| Layer::new(origin, Map::new())))
+}
+
+/// The values shared by every profile, or the ones of `profile`
+fn scope<'a>(file: &'a mut Layer, profile: Option<&str>) -> &'a mut Map<String, Value> {
+    match profile {
+        Some(name) => file.profiles.entry(name.to_string()).or_default(),
+        None => &mut file.values,
+    }
+}
+
+fn known_key(key: Option<&String>) -> Result<String, LateremError> {
+    let keys = DefaultConfig::keys();
+
+    match key {
+        Some(key) if keys.contains(key) => Ok(key.clone()),
+        Some(key) => Err(LateremError::InvalidArgument(describe_unknown(key, &keys))),
+        None => Err(LateremError::InvalidArgument(format!(
+            "missing the config key, expected one of: {}",
+            keys.join(", ")
+        ))),
+    }
+}
+
+/// Explain an unknown key, suggesting the closest known one when it looks like a typo
+pub fn describe_unknown(key: &str, keys: &[String]) -> String {
+    let closest = keys
+        .iter()
+        .map(|known| (distance(key, known), known))
+        .min()
+        .filter(|(distance, _)| *distance <= 3);
+
+    match closest {
+        Some((_, known)) => format!("unknown key `{}`, did you mean `{}`?", key, known),
+        None => format!(
+            "unknown key `{}`, expected one of: {}",
+            key,
+            keys.join(", ")
+        ),
+    }
+}
+
+/// Levenshtein distance between two keys
+fn distance(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+
+    for (i, left) in left.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, right) in right.iter().enumerate() {
+            let substitution = previous[j] + usize::from(left != *right);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[right.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::utils::entities::Target;
+
+    fn config(path: &Path, action: Action, values: &[&str]) -> Config {
+        Config {
+            path: path.to_path_buf(),
+            values: values.iter().map(|value| value.to_string()).collect(),
+            ..Config::for_test(Target::Config, action)
+        }
+    }
+
+    fn document(path: &Path) -> Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn set_and_unset_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.json");
+
+        run(&mut config(&path, Action::Set, &["branch", "develop"])).unwrap();
+        run(&mut config(&path, Action::Set, &["wait_timeout", "30"])).unwrap();
+        run(&mut config(&path, Action::Set, &["project_name", "2024"])).unwrap();
+
+        assert_eq!(document(&path)["branch"], json!("develop"));
+        assert_eq!(document(&path)["wait_timeout"], json!(30));
+        assert_eq!(document(&path)["project_name"], json!("2024"));
+
+        run(&mut config(&path, Action::Unset, &["branch"])).unwrap();
+        run(&mut config(&path, Action::Unset, &["branch"])).unwrap();
+
+        assert!(document(&path).get("branch").is_none());
+        assert_eq!(document(&path)["wait_timeout"], json!(30));
+    }
+
+    #[test]
+    fn profile_values_are_set_apart() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.json");
+        let mut ci = config(&path, Action::Set, &["remote", "upstream"]);
+        ci.profile = Some(String::from("ci"));
+
+        run(&mut ci).unwrap();
+
+        let file = read(&path, Origin::Global(path.clone())).unwrap();
+        assert!(file.values.is_empty());
+        assert_eq!(file.profiles["ci"]["remote"], json!("upstream"));
+
+        ci.action = Action::Unset;
+        run(&mut ci).unwrap();
+
+        let file = read(&path, Origin::Global(path.clone())).unwrap();
+        assert!(file.profiles.get("ci").is_none_or(Map::is_empty));
+    }
+
+    #[test]
+    fn invalid_values_are_not_written() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.json");
+
+        let error = run(&mut config(&path, Action::Set, &["wait_timeout", "soon"]))
+            .err()
+            .unwrap();
+
+        assert!(matches!(error, LateremError::InvalidArgument(_)));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn unknown_keys_suggest_the_closest_one() {
+        let keys = DefaultConfig::keys();
+
+        assert_eq!(
+            describe_unknown("brnach", &keys),
+            "unknown key `brnach`, did you mean `branch`?"
+        );
+        assert!(describe_unknown("colour_scheme", &keys).contains("expected one of: branch"));
+        assert!(matches!(
+            known_key(Some(&String::from("remtoe"))),
+            Err(LateremError::InvalidArgument(message)) if message.contains("`remote`")
+        ));
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "remote"), 6);
+    }
+
+    #[test]
+    fn validate_rejects_unknown_keys_and_invalid_values() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.json");
+        let origin = || Origin::Global(path.clone());
+
+        assert!(validate(&path, origin()).is_ok());
+
+        for (document, expected) in [
+            (json!({ "brnach": "main" }), "did you mean `branch`?"),
+            (json!({ "wait_timeout": "soon" }), "expected u64"),
+            (
+                json!({ "profiles": { "ci": { "stash_files": "yes" } } }),
+                "(profile ci)",
+            ),
+        ] {
+            fs::write(&path, document.to_string()).unwrap();
+
+            match validate(&path, origin()) {
+                Err(LateremError::InvalidConfig(message)) => {
+                    assert!(message.contains(expected), "{}", message)
+                }
+                _ => panic!("{} should be invalid", document),
+            }
+        }
+    }
+
+    #[test]
+    fn repository_files_are_labelled_as_such() {
+        let config = config(Path::new("config.json"), Action::List, &[]);
+
+        assert_eq!(
+            file_origin(&config, Path::new("config.json")).to_string(),
+            "global config.json"
+        );
+        assert_eq!(
+            file_origin(&config, Path::new(".laterem.json")).to_string(),
+            "repository .laterem.json"
+        );
+    }
+}
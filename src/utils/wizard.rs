@@ -0,0 +1,161 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    path::Path,
+};
+
+use crossterm::style::Stylize;
+use serde_json::{Map, Value};
+
+use super::{
+    entities::{DefaultConfig, LateremError, SyncStrategy},
+    layers,
+};
+
+/// Create the first config file at `path`, prompting for every field unless `interactive` is
+/// disabled or stdin isn't a terminal, and return the values written
+///
+/// Only the answers that differ from the detected defaults are written, so the default branch
+/// keeps being detected per repository unless the user picked another one
+pub fn create(path: &Path, interactive: bool) -> Result<Map<String, Value>, LateremError> {
+    let detected = DefaultConfig::default();
+    let mut defaults = DefaultConfig::default();
+
+    if interactive && io::stdin().is_terminal() {
+        prompt(path, &mut defaults).map_err(|source| LateremError::ConfigIo {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    }
+
+    let values = changed(&detected, &defaults);
+    layers::write(path, &values)?;
+
+    println!(
+        " - {} {}\t\n",
+        "Config written to:".dark_magenta().bold(),
+        path.display(),
+    );
+
+    Ok(values)
+}
+
+/// The values of `chosen` that differ from `detected`
+fn changed(detected: &DefaultConfig, chosen: &DefaultConfig) -> Map<String, Value> {
+    let detected = detected.values();
+
+    chosen
+        .values()
+        .into_iter()
+        .filter(|(key, value)| detected.get(key) != Some(value))
+        .collect()
+}
+
+fn prompt(path: &Path, defaults: &mut DefaultConfig) -> io::Result<()> {
+    println!(
+        "{}\t\n",
+        " FIRST RUN SETUP ".bold().on_dark_magenta().white()
+    );
+    println!(
+        " {} {}\t\n",
+        "No config file found, creating".dim(),
+        path.display().to_string().magenta(),
+    );
+
+    defaults.branch = ask("Default branch", &defaults.branch)?;
+    defaults.stash_files = confirm("Stash changes before pulling", defaults.stash_files)?;
+    defaults.detach_container = confirm(
+        "Start containers in detached mode",
+        defaults.detach_container,
+    )?;
+
+    defaults.sync_strategy = match confirm(
+        "Rebase instead of merging when syncing",
+        matches!(defaults.sync_strategy, SyncStrategy::Rebase),
+    )? {
+        true => SyncStrategy::Rebase,
+        false => SyncStrategy::Merge,
+    };
+
+    defaults.compose_files = ask(
+        "Compose files, comma separated (empty for docker's default)",
+        "",
+    )?
+    .split(',')
+    .map(str::trim)
+    .filter(|file| !file.is_empty())
+    .map(String::from)
+    .collect();
+
+    println!();
+
+    Ok(())
+}
+
+fn ask(question: &str, default: &str) -> io::Result<String> {
+    if default.is_empty() {
+        print!(" {} ", question.bold());
+    } else {
+        print!(" {} {} ", question.bold(), format!("[{}]", default).dim());
+    }
+
+    let answer = read_line()?;
+
+    if answer.is_empty() {
+        return Ok(default.to_string());
+    }
+
+    Ok(answer)
+}
+
+pub fn confirm(question: &str, default: bool) -> io::Result<bool> {
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+
+    loop {
+        print!(" {} {} ", question.bold(), hint.dim());
+
+        match read_line()?.to_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!(" {}", "Please answer with `y` or `n`".dark_red()),
+        }
+    }
+}
+
+fn read_line() -> io::Result<String> {
+    let mut answer = String::new();
+
+    io::stdout().flush()?;
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(answer.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults(branch: &str) -> DefaultConfig {
+        let mut defaults = DefaultConfig::check(&Map::new()).unwrap();
+        defaults.branch = branch.to_string();
+        defaults
+    }
+
+    #[test]
+    fn accepted_defaults_are_left_out() {
+        assert!(changed(&defaults("main"), &defaults("main")).is_empty());
+    }
+
+    #[test]
+    fn changed_answers_are_kept() {
+        let mut chosen = defaults("develop");
+        chosen.stash_files = false;
+
+        let values = changed(&defaults("main"), &chosen);
+
+        assert_eq!(
+            Value::Object(values),
+            serde_json::json!({ "branch": "develop", "stash_files": false })
+        );
+    }
+}
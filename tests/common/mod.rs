@@ -0,0 +1,226 @@
+//! Throwaway git repositories and a stub `docker` binary for end-to-end runs of laterem
+
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use tempfile::TempDir;
+
+/// A clone of a local bare `origin`, with its own home directory and a `docker` stub that logs
+/// its arguments instead of talking to a daemon, printing the reply and exiting with the status
+/// recorded for them if any
+pub struct Sandbox {
+    root: TempDir,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        let sandbox = Self {
+            root: tempfile::tempdir().expect("Unable to create the sandbox directory"),
+        };
+
+        fs::create_dir_all(sandbox.home()).unwrap();
+        fs::write(
+            sandbox.home().join(".gitconfig"),
+            "[user]\n\tname = Laterem\n\temail = laterem@example.com\n\
+             [pull]\n\trebase = false\n[advice]\n\tdetachedHead = false\n",
+        )
+        .unwrap();
+
+        fs::create_dir_all(sandbox.bin()).unwrap();
+        let docker = sandbox.bin().join("docker");
+        fs::write(
+            &docker,
+            format!(
+                "#!/bin/sh\necho \"$*\" >> \"{}\"\n\
+                 reply=\"{}/$(echo \"$*\" | tr ' /' '__')\"\n\
+                 if [ -f \"$reply\" ]; then cat \"$reply\"; fi\n\
+                 if [ -f \"$reply.status\" ]; then exit \"$(cat \"$reply.status\")\"; fi\n",
+                sandbox.docker_log().display(),
+                sandbox.docker_replies().display(),
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&docker, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::create_dir_all(sandbox.docker_replies()).unwrap();
+
+        sandbox.git(
+            sandbox.root(),
+            &["init", "--bare", "-b", "main", "origin.git"],
+        );
+        sandbox.git(sandbox.root(), &["clone", "origin.git", "work"]);
+
+        sandbox.write("README.md", "laterem\n");
+        sandbox.git(&sandbox.work(), &["add", "."]);
+        sandbox.git(&sandbox.work(), &["commit", "-m", "initial commit"]);
+        sandbox.git(&sandbox.work(), &["push", "-u", "origin", "main"]);
+        // Cloning the empty origin left `origin/HEAD` unset, unlike a regular clone
+        sandbox.git(&sandbox.work(), &["remote", "set-head", "origin", "main"]);
+
+        sandbox
+    }
+
+    pub fn root(&self) -> &Path {
+        self.root.path()
+    }
+
+    pub fn home(&self) -> PathBuf {
+        self.root().join("home")
+    }
+
+    pub fn bin(&self) -> PathBuf {
+        self.root().join("bin")
+    }
+
+    pub fn work(&self) -> PathBuf {
+        self.root().join("work")
+    }
+
+    pub fn config(&self) -> PathBuf {
+        self.home().join("config.json")
+    }
+
+    fn docker_log(&self) -> PathBuf {
+        self.root().join("docker.log")
+    }
+
+    fn docker_replies(&self) -> PathBuf {
+        self.root().join("docker-replies")
+    }
+
+    fn command(&self, program: &str, directory: &Path) -> Command {
+        let path = std::env::var("PATH").unwrap_or_default();
+        let mut command = Command::new(program);
+
+        command
+            .current_dir(directory)
+            .env("HOME", self.home())
+            .env("PATH", format!("{}:{}", self.bin().display(), path))
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CEILING_DIRECTORIES", self.root())
+            .env_remove("XDG_CONFIG_HOME");
+
+        for (name, _) in std::env::vars() {
+            if name.starts_with("LATEREM_") {
+                command.env_remove(name);
+            }
+        }
+
+        command
+    }
+
+    /// Run git inside `directory`, panicking when it fails
+    pub fn git(&self, directory: &Path, args: &[&str]) -> String {
+        let output = self
+            .command("git", directory)
+            .args(args)
+            .output()
+            .expect("Unable to spawn git");
+
+        assert!(
+            output.status.success(),
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// Run the laterem binary inside the working clone
+    pub fn laterem(&self, args: &[&str]) -> Output {
+        self.laterem_in(&self.work(), args)
+    }
+
+    /// Run the laterem binary inside `directory`
+    pub fn laterem_in(&self, directory: &Path, args: &[&str]) -> Output {
+        // The global options go first, so they don't end up in the command of `exec -- ...`
+        self.command(env!("CARGO_BIN_EXE_laterem"), directory)
+            .arg("--config")
+            .arg(self.config())
+            .arg("--non-interactive")
+            .args(args)
+            .output()
+            .expect("Unable to spawn laterem")
+    }
+
+    /// Write a file relative to the working clone
+    pub fn write(&self, file: &str, content: &str) {
+        fs::write(self.work().join(file), content).unwrap();
+    }
+
+    pub fn read(&self, file: &str) -> String {
+        fs::read_to_string(self.work().join(file)).unwrap()
+    }
+
+    /// Push a new commit to `branch` of origin from a separate clone
+    pub fn upstream_commit(&self, branch: &str, file: &str, content: &str) -> String {
+        let upstream = self.root().join("upstream");
+
+        if !upstream.exists() {
+            self.git(self.root(), &["clone", "origin.git", "upstream"]);
+        }
+
+        self.git(&upstream, &["fetch", "origin"]);
+        self.git(
+            &upstream,
+            &["checkout", "-B", branch, &format!("origin/{}", branch)],
+        );
+        fs::write(upstream.join(file), content).unwrap();
+        self.git(&upstream, &["add", "."]);
+        self.git(&upstream, &["commit", "-m", &format!("update {}", file)]);
+        self.git(&upstream, &["push", "origin", branch]);
+
+        self.git(&upstream, &["rev-parse", "HEAD"])
+    }
+
+    pub fn current_branch(&self) -> String {
+        self.git(&self.work(), &["branch", "--show-current"])
+    }
+
+    pub fn stash_list(&self) -> Vec<String> {
+        self.git(&self.work(), &["stash", "list"])
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    /// Make the `docker` stub print `output` when it's called with exactly `args`
+    pub fn docker_reply(&self, args: &str, output: &str) {
+        let name = args.replace([' ', '/'], "_");
+
+        fs::write(self.docker_replies().join(name), output).unwrap();
+    }
+
+    /// Make the `docker` stub exit with `code` when it's called with exactly `args`
+    pub fn docker_status(&self, args: &str, code: i32) {
+        let name = format!("{}.status", args.replace([' ', '/'], "_"));
+
+        fs::write(self.docker_replies().join(name), code.to_string()).unwrap();
+    }
+
+    /// Every `docker` invocation, one line of arguments each
+    pub fn docker_calls(&self) -> Vec<String> {
+        fs::read_to_string(self.docker_log())
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// Assert that laterem exited with `code`, printing its output otherwise
+pub fn assert_exit(output: &Output, code: i32) {
+    assert_eq!(
+        output.status.code(),
+        Some(code),
+        "stdout:\n{}\nstderr:\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,252 @@
+mod common;
+
+use common::{assert_exit, Sandbox};
+
+#[test]
+fn reset_takes_the_stack_down_then_up() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.laterem(&["d"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(
+        sandbox.docker_calls(),
+        [
+            "compose down",
+            "compose up -d",
+            "compose ps --all --format json"
+        ]
+    );
+}
+
+#[test]
+fn up_starts_detached_containers() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.laterem(&["d", "u"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(
+        sandbox.docker_calls(),
+        ["compose up -d", "compose ps --all --format json"]
+    );
+}
+
+#[test]
+fn down_stops_the_stack() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.laterem(&["d", "d"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(sandbox.docker_calls(), ["compose down"]);
+}
+
+#[test]
+fn config_file_selects_the_compose_file_and_attached_mode() {
+    let sandbox = Sandbox::new();
+    std::fs::write(
+        sandbox.config(),
+        r#"{ "version": 1, "compose_file": "docker-compose.dev.yml", "detach_container": false }"#,
+    )
+    .unwrap();
+
+    let output = sandbox.laterem(&["d"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(
+        sandbox.docker_calls(),
+        [
+            "compose -f docker-compose.dev.yml down",
+            "compose -f docker-compose.dev.yml up",
+        ]
+    );
+}
+
+#[test]
+fn flags_select_the_compose_project() {
+    let sandbox = Sandbox::new();
+    std::fs::write(
+        sandbox.config(),
+        r#"{ "version": 2, "compose_files": ["compose.yml"], "project_name": "laterem" }"#,
+    )
+    .unwrap();
+
+    let output = sandbox.laterem(&[
+        "d",
+        "down",
+        "-f",
+        "compose.yml",
+        "-f",
+        "compose.dev.yml",
+        "--env-file",
+        ".env.dev",
+        "--working-directory",
+        "services",
+    ]);
+
+    assert_exit(&output, 0);
+    assert_eq!(
+        sandbox.docker_calls(),
+        [
+            "compose --project-directory services -f compose.yml -f compose.dev.yml -p laterem \
+          --env-file .env.dev down"
+        ]
+    );
+}
+
+#[test]
+fn services_scope_the_reset() {
+    let sandbox = Sandbox::new();
+    sandbox.docker_reply("compose config --services", "api\ndatabase\nworker\n");
+
+    let output = sandbox.laterem(&["d", "reset", "api", "--args", "worker"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(
+        sandbox.docker_calls(),
+        [
+            "compose config --services",
+            "compose down api worker",
+            "compose up -d api worker",
+            "compose ps --all --format json api worker",
+        ]
+    );
+}
+
+#[test]
+fn stopped_service_fails_the_up_with_its_logs() {
+    let sandbox = Sandbox::new();
+    sandbox.docker_reply(
+        "compose ps --all --format json",
+        r#"{"Service":"database","State":"exited","ExitCode":1}"#,
+    );
+    sandbox.docker_reply(
+        "compose logs --no-color --tail 20 database",
+        "database  | FATAL: invalid configuration\n",
+    );
+
+    let output = sandbox.laterem(&["d", "up"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_exit(&output, 5);
+    assert!(stdout.contains("FATAL: invalid configuration"));
+    assert!(
+        stdout.contains("`database` exited with an error"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("wait_timeout"), "{}", stdout);
+}
+
+#[test]
+fn status_prints_the_services() {
+    let sandbox = Sandbox::new();
+    sandbox.docker_reply(
+        "compose ps --all --format json",
+        r#"{"Service":"database","State":"running","Health":"healthy","Publishers":[{"TargetPort":5432,"PublishedPort":5433,"Protocol":"tcp"}]}"#,
+    );
+
+    let output = sandbox.laterem(&["d", "status"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_exit(&output, 0);
+    assert!(stdout.contains("database"));
+    assert!(stdout.contains("5433->5432/tcp"));
+    assert_eq!(sandbox.docker_calls(), ["compose ps --all --format json"]);
+}
+
+#[test]
+fn logs_and_exec_run_in_the_given_service() {
+    let sandbox = Sandbox::new();
+    sandbox.docker_reply("compose config --services", "api\n");
+
+    assert_exit(&sandbox.laterem(&["d", "logs", "api", "--since", "5m"]), 0);
+    assert_exit(
+        &sandbox.laterem(&["d", "exec", "api", "--", "ls", "-la"]),
+        0,
+    );
+
+    assert_eq!(
+        sandbox.docker_calls(),
+        [
+            "compose config --services",
+            "compose logs --since 5m api",
+            "compose config --services",
+            "compose exec api ls -la",
+        ]
+    );
+}
+
+#[test]
+fn exec_exits_with_the_status_of_the_command() {
+    let sandbox = Sandbox::new();
+    sandbox.docker_reply("compose config --services", "api\n");
+    sandbox.docker_status("compose exec api false", 3);
+
+    let output = sandbox.laterem(&["d", "exec", "api", "--", "false"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_exit(&output, 3);
+    assert!(!stdout.contains("ERROR OUTPUT"), "{}", stdout);
+}
+
+#[test]
+fn hard_reset_destroys_the_volumes_and_rebuilds() {
+    let sandbox = Sandbox::new();
+    sandbox.docker_reply(
+        "compose config --format json",
+        r#"{"name":"api","volumes":{"pgdata":{"name":"api_pgdata"}}}"#,
+    );
+
+    let output = sandbox.laterem(&["d", "reset", "--hard", "--no-cache", "--yes"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_exit(&output, 0);
+    assert!(stdout.contains("api_pgdata"));
+    assert_eq!(
+        sandbox.docker_calls(),
+        [
+            "compose config --format json",
+            "compose down --volumes --remove-orphans",
+            "compose build --no-cache",
+            "compose up -d",
+            "compose ps --all --format json",
+        ]
+    );
+}
+
+#[test]
+fn nuke_without_a_terminal_needs_yes() {
+    let sandbox = Sandbox::new();
+    sandbox.docker_reply("compose config --format json", r#"{"name":"api"}"#);
+
+    let output = sandbox.laterem(&["d", "nuke"]);
+
+    assert_exit(&output, 2);
+    assert_eq!(sandbox.docker_calls(), ["compose config --format json"]);
+}
+
+#[test]
+fn unknown_service_is_a_usage_error() {
+    let sandbox = Sandbox::new();
+    sandbox.docker_reply("compose config --services", "api\n");
+
+    let output = sandbox.laterem(&["d", "up", "apu"]);
+
+    assert_exit(&output, 2);
+    assert_eq!(sandbox.docker_calls(), ["compose config --services"]);
+}
+
+#[test]
+fn dry_run_never_calls_docker() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.laterem(&["d", "--dry-run"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_exit(&output, 0);
+    assert!(stdout.contains("docker compose down"));
+    assert!(stdout.contains("docker compose up -d"));
+    assert!(sandbox.docker_calls().is_empty());
+}
@@ -0,0 +1,367 @@
+mod common;
+
+use common::{assert_exit, Sandbox};
+
+#[test]
+fn reset_updates_the_default_branch_and_restores_the_work() {
+    let sandbox = Sandbox::new();
+    let upstream = sandbox.upstream_commit("main", "upstream.txt", "new\n");
+
+    sandbox.git(&sandbox.work(), &["checkout", "-b", "feature"]);
+    sandbox.write("README.md", "work in progress\n");
+
+    let output = sandbox.laterem(&["r"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(sandbox.current_branch(), "feature");
+    assert_eq!(
+        sandbox.git(&sandbox.work(), &["rev-parse", "main"]),
+        upstream
+    );
+    assert_eq!(sandbox.read("README.md"), "work in progress\n");
+    assert!(sandbox.stash_list().is_empty());
+}
+
+#[test]
+fn pull_updates_the_current_branch_and_restores_the_work() {
+    let sandbox = Sandbox::new();
+    let upstream = sandbox.upstream_commit("main", "upstream.txt", "new\n");
+
+    sandbox.write("README.md", "work in progress\n");
+
+    let output = sandbox.laterem(&["r", "pl"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(sandbox.current_branch(), "main");
+    assert_eq!(
+        sandbox.git(&sandbox.work(), &["rev-parse", "HEAD"]),
+        upstream
+    );
+    assert_eq!(sandbox.read("upstream.txt"), "new\n");
+    assert_eq!(sandbox.read("README.md"), "work in progress\n");
+    assert!(sandbox.stash_list().is_empty());
+}
+
+#[test]
+fn commit_records_the_staged_changes() {
+    let sandbox = Sandbox::new();
+
+    sandbox.write("README.md", "committed\n");
+    sandbox.git(&sandbox.work(), &["add", "README.md"]);
+
+    let output = sandbox.laterem(&["r", "c", "--args", "docs: update readme"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(
+        sandbox.git(&sandbox.work(), &["log", "-1", "--format=%s"]),
+        "docs: update readme"
+    );
+    assert!(sandbox
+        .git(&sandbox.work(), &["status", "--porcelain"])
+        .is_empty());
+}
+
+#[test]
+fn commit_without_a_message_is_a_usage_error() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.laterem(&["r", "c"]);
+
+    assert_exit(&output, 2);
+}
+
+#[test]
+fn push_sends_the_current_branch_to_origin() {
+    let sandbox = Sandbox::new();
+
+    sandbox.git(&sandbox.work(), &["checkout", "-b", "feature"]);
+    sandbox.write("feature.txt", "feature\n");
+    sandbox.git(&sandbox.work(), &["add", "."]);
+    sandbox.git(&sandbox.work(), &["commit", "-m", "add feature"]);
+
+    let output = sandbox.laterem(&["r", "ps"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(
+        sandbox.git(
+            &sandbox.root().join("origin.git"),
+            &["rev-parse", "feature"]
+        ),
+        sandbox.git(&sandbox.work(), &["rev-parse", "HEAD"])
+    );
+}
+
+#[test]
+fn failed_step_aborts_with_the_git_exit_code() {
+    let sandbox = Sandbox::new();
+
+    sandbox.git(&sandbox.work(), &["checkout", "-b", "feature"]);
+
+    // `feature` was never pushed, so pulling it fails
+    let output = sandbox.laterem(&["r", "pl"]);
+
+    assert_exit(&output, 4);
+}
+
+#[test]
+fn dry_run_prints_the_plan_without_touching_the_repository() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream_commit("main", "upstream.txt", "new\n");
+    let main = sandbox.git(&sandbox.work(), &["rev-parse", "main"]);
+
+    sandbox.git(&sandbox.work(), &["checkout", "-b", "feature"]);
+    sandbox.write("README.md", "work in progress\n");
+
+    let output = sandbox.laterem(&["r", "--dry-run"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_exit(&output, 0);
+    assert!(stdout.contains("git stash"));
+    assert!(stdout.contains("git checkout main"));
+    assert!(stdout.contains("git pull origin main"));
+    assert!(stdout.contains("git checkout feature"));
+    assert_eq!(sandbox.current_branch(), "feature");
+    assert_eq!(sandbox.git(&sandbox.work(), &["rev-parse", "main"]), main);
+    assert_eq!(sandbox.read("README.md"), "work in progress\n");
+    assert!(sandbox.stash_list().is_empty());
+    assert!(!sandbox.config().exists());
+}
+
+#[test]
+fn failed_reset_returns_to_the_original_branch_with_the_work() {
+    let sandbox = Sandbox::new();
+    std::fs::write(
+        sandbox.config(),
+        r#"{ "version": 1, "branch": "main", "remote": "nowhere" }"#,
+    )
+    .unwrap();
+
+    sandbox.git(&sandbox.work(), &["checkout", "-b", "feature"]);
+    sandbox.write("README.md", "work in progress\n");
+
+    let output = sandbox.laterem(&["r"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_exit(&output, 4);
+    assert!(stdout.contains("ROLLING BACK"));
+    assert_eq!(sandbox.current_branch(), "feature");
+    assert_eq!(sandbox.read("README.md"), "work in progress\n");
+    assert!(sandbox.stash_list().is_empty());
+}
+
+#[test]
+fn clean_tree_leaves_older_stashes_alone() {
+    let sandbox = Sandbox::new();
+
+    sandbox.write("README.md", "older work\n");
+    sandbox.git(&sandbox.work(), &["stash", "push", "-m", "older work"]);
+    sandbox.git(&sandbox.work(), &["checkout", "-b", "feature"]);
+
+    let output = sandbox.laterem(&["r"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(sandbox.stash_list(), ["stash@{0}: On main: older work"]);
+    assert_eq!(sandbox.read("README.md"), "laterem\n");
+}
+
+#[test]
+fn current_reset_pulls_the_current_branch() {
+    let sandbox = Sandbox::new();
+
+    sandbox.git(&sandbox.work(), &["checkout", "-b", "feature"]);
+    sandbox.git(&sandbox.work(), &["push", "-u", "origin", "feature"]);
+    let upstream = sandbox.upstream_commit("feature", "upstream.txt", "new\n");
+    sandbox.write("README.md", "work in progress\n");
+
+    let output = sandbox.laterem(&["rc"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(sandbox.current_branch(), "feature");
+    assert_eq!(
+        sandbox.git(&sandbox.work(), &["rev-parse", "HEAD"]),
+        upstream
+    );
+    assert_eq!(sandbox.read("README.md"), "work in progress\n");
+    assert!(sandbox.stash_list().is_empty());
+}
+
+#[test]
+fn sync_rebases_the_current_branch_onto_the_updated_default_branch() {
+    let sandbox = Sandbox::new();
+    let upstream = sandbox.upstream_commit("main", "upstream.txt", "new\n");
+
+    sandbox.git(&sandbox.work(), &["checkout", "-b", "feature"]);
+    sandbox.write("feature.txt", "feature\n");
+    sandbox.git(&sandbox.work(), &["add", "."]);
+    sandbox.git(&sandbox.work(), &["commit", "-m", "add feature"]);
+    sandbox.write("README.md", "work in progress\n");
+
+    let output = sandbox.laterem(&["r", "sync"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(sandbox.current_branch(), "feature");
+    assert_eq!(
+        sandbox.git(&sandbox.work(), &["rev-parse", "HEAD~1"]),
+        upstream
+    );
+    assert_eq!(sandbox.read("README.md"), "work in progress\n");
+    assert!(sandbox.stash_list().is_empty());
+}
+
+#[test]
+fn conflicting_sync_stops_with_the_work_kept_aside() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream_commit("main", "shared.txt", "upstream\n");
+
+    sandbox.git(&sandbox.work(), &["checkout", "-b", "feature"]);
+    sandbox.write("shared.txt", "feature\n");
+    sandbox.git(&sandbox.work(), &["add", "."]);
+    sandbox.git(&sandbox.work(), &["commit", "-m", "add shared"]);
+    sandbox.write("README.md", "work in progress\n");
+
+    let output = sandbox.laterem(&["r", "reset", "--rebase"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_exit(&output, 4);
+    assert!(stdout.contains("stopped on conflicts"), "{}", stdout);
+    assert!(stdout.contains("git stash pop stash@{0}"), "{}", stdout);
+    assert!(sandbox.work().join(".git/rebase-merge").exists());
+    assert_eq!(sandbox.stash_list().len(), 1);
+}
+
+#[test]
+fn actions_of_other_targets_are_usage_errors() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.laterem(&["r", "up"]);
+
+    assert_exit(&output, 2);
+    assert!(sandbox.stash_list().is_empty());
+}
+
+#[test]
+fn default_branch_is_found_without_origin_head() {
+    let sandbox = Sandbox::new();
+    let upstream = sandbox.upstream_commit("main", "upstream.txt", "new\n");
+
+    sandbox.git(
+        &sandbox.work(),
+        &["remote", "set-head", "origin", "--delete"],
+    );
+    sandbox.git(&sandbox.work(), &["checkout", "-b", "feature"]);
+
+    let output = sandbox.laterem(&["r"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(
+        sandbox.git(&sandbox.work(), &["rev-parse", "main"]),
+        upstream
+    );
+    assert_eq!(
+        sandbox.git(&sandbox.work(), &["config", "laterem.defaultBranch"]),
+        "main"
+    );
+}
+
+#[test]
+fn first_run_leaves_the_default_branch_to_detection() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.laterem(&["r"]);
+    let config = std::fs::read_to_string(sandbox.config()).unwrap();
+
+    assert_exit(&output, 0);
+    assert!(!config.contains("branch"), "{}", config);
+}
+
+#[test]
+fn only_a_real_run_caches_the_default_branch() {
+    let sandbox = Sandbox::new();
+    let git_config = sandbox.work().join(".git").join("config");
+    sandbox.git(
+        &sandbox.work(),
+        &["remote", "set-head", "origin", "--delete"],
+    );
+    let before = std::fs::read_to_string(&git_config).unwrap();
+
+    assert_exit(&sandbox.laterem(&["r", "--dry-run"]), 0);
+    assert_exit(&sandbox.laterem(&["config", "list"]), 0);
+
+    assert_eq!(std::fs::read_to_string(&git_config).unwrap(), before);
+}
+
+#[test]
+fn config_changes_cannot_be_dry_runs() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.laterem(&["config", "set", "remote", "upstream", "--dry-run"]);
+
+    assert_exit(&output, 2);
+    assert!(!sandbox.config().exists());
+}
+
+#[test]
+fn validate_and_dry_runs_leave_outdated_configs_alone() {
+    let sandbox = Sandbox::new();
+    let original = r#"{ "version": 1, "branch": "main", "compose_file": "compose.yml" }"#;
+    std::fs::write(sandbox.config(), original).unwrap();
+
+    for args in [&["config", "validate"][..], &["r", "--dry-run"]] {
+        let output = sandbox.laterem(args);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        assert_exit(&output, 0);
+        assert!(stdout.contains("Outdated config version 1"), "{}", stdout);
+    }
+
+    assert_eq!(std::fs::read_to_string(sandbox.config()).unwrap(), original);
+
+    assert_exit(&sandbox.laterem(&["r"]), 0);
+
+    let upgraded = std::fs::read_to_string(sandbox.config()).unwrap();
+    assert!(upgraded.contains("compose_files"), "{}", upgraded);
+}
+
+#[test]
+fn reading_the_config_never_writes_it() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.laterem(&["config", "get", "remote"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "origin\n");
+    assert_exit(&sandbox.laterem(&["config", "list"]), 0);
+    assert!(!sandbox.config().exists());
+
+    let original = r#"{ "version": 1, "remote": "upstream", "compose_file": "compose.yml" }"#;
+    std::fs::write(sandbox.config(), original).unwrap();
+
+    let output = sandbox.laterem(&["config", "get", "compose_files"]);
+
+    assert_exit(&output, 0);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "[\"compose.yml\"]\n"
+    );
+
+    let output = sandbox.laterem(&["config", "list"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_exit(&output, 0);
+    assert!(stdout.contains("upstream"), "{}", stdout);
+    assert!(!stdout.contains("version"), "{}", stdout);
+    assert_eq!(std::fs::read_to_string(sandbox.config()).unwrap(), original);
+    assert!(!sandbox.home().join("config.json.bak").exists());
+}
+
+#[test]
+fn reset_outside_a_repository_says_so() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.laterem_in(sandbox.root(), &["r"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_exit(&output, 4);
+    assert!(stdout.contains("not inside a git repository"), "{}", stdout);
+    assert!(!stdout.contains("config set branch"), "{}", stdout);
+}